@@ -8,16 +8,20 @@ use axum::{
 use std::sync::Arc;
 use tower::ServiceBuilder;
 
-use crate::config;
-
 use rand::Rng;
 use serde_json::json;
 
 use crate::{
+    auth::jwt::Keys,
+    config::Config,
     ctx::with_ctx,
     db::DB,
     errors::{self, on_error},
-    openapi::{aide::axum::ApiRouter, OpenApi},
+    openapi::{
+        aide::{axum::ApiRouter, openapi::SecurityScheme},
+        OpenApi,
+    },
+    policy::SECURITY_SCHEME,
     state::AppState,
 };
 
@@ -26,16 +30,29 @@ where
     Router: FnOnce(AppState) -> ApiRouter,
 {
     pub db: DB,
+    pub config: Arc<Config>,
+    pub keys: Arc<Keys>,
     pub router: Router,
 }
 
-pub async fn create<R>(AppParams { db, router }: AppParams<R>) -> errors::Result<(Router, OpenApi)>
+pub async fn create<R>(
+    AppParams {
+        db,
+        config,
+        keys,
+        router,
+    }: AppParams<R>,
+) -> errors::Result<(Router, OpenApi)>
 where
     R: FnOnce(AppState) -> ApiRouter,
 {
     let mut api = OpenApi::default();
 
-    let state = AppState { conn: db.clone() };
+    let state = AppState {
+        conn: db.clone(),
+        config,
+        keys,
+    };
 
     let api_router = axum::Router::new().route(
         "/__docs__",
@@ -54,10 +71,21 @@ where
         .route("/__lbheartbeat__", get(lbheartbeat))
         .merge(api_router)
         .merge(router(state.clone()))
-        .finish_api_with(&mut api, |t| t.title("Notes"))
+        .finish_api_with(&mut api, |t| {
+            t.title("Notes").security_scheme(
+                SECURITY_SCHEME,
+                SecurityScheme::Http {
+                    scheme: "bearer".into(),
+                    bearer_format: None,
+                    description: Some("Session token, also accepted as the `session` cookie".into()),
+                    extensions: Default::default(),
+                },
+            )
+        })
         .layer(
             ServiceBuilder::new()
                 .layer(Extension(db))
+                .layer(Extension(state.clone()))
                 .layer(Extension(Arc::new(api.clone())))
                 .layer(middleware::from_fn(with_ctx))
                 .layer(middleware::from_fn(on_error)),
@@ -66,8 +94,7 @@ where
     Ok((app, api))
 }
 
-async fn version() -> impl IntoResponse {
-    let config = &config();
+async fn version(Extension(AppState { config, .. }): Extension<AppState>) -> impl IntoResponse {
     Json(json!({
         "source" : config.source,
         "version": config.version,
@@ -93,5 +120,5 @@ async fn lbheartbeat() -> impl IntoResponse {
 // TODO
 #[cfg(not(test))]
 async fn serve_docs(Extension(api): Extension<Arc<OpenApi>>) -> impl aide::axum::IntoApiResponse {
-    crate::openapi::Json(api).into_response()
+    crate::openapi::Json(api.as_ref()).into_response()
 }
@@ -0,0 +1,273 @@
+use axum::{
+    body::Bytes,
+    extract::multipart::{Field, Multipart},
+};
+use futures_util::{stream, Stream};
+use rusqlite::{params, DatabaseName, OptionalExtension, Row};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    ctx::BaseParams,
+    db,
+    notes::{require_access, Access},
+    Error, Result, DB,
+};
+
+use super::{Attachment, FindAttachmentsResponse};
+
+/// Bytes read from the database per chunk of a download.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+impl<'a> TryFrom<&Row<'a>> for Attachment {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            file_name: row.get(2)?,
+            content_type: row.get(3)?,
+            size: row.get(4)?,
+            checksum: row.get(5)?,
+            created_at: row.get(6)?,
+            created_by: row.get(7)?,
+        })
+    }
+}
+
+pub async fn find_attachments(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<FindAttachmentsResponse> {
+    db.call(move |conn| {
+        require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+        let attachments = conn
+            .prepare(
+                r#"SELECT id, note_id, file_name, content_type, size, checksum, created_at, created_by FROM attachments
+                WHERE note_id = ? AND checksum IS NOT NULL
+                ORDER BY id"#,
+            )?
+            .query_map(params![note_id], |row| Attachment::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindAttachmentsResponse { results: attachments })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Attachment and the rowid its content is read through.
+async fn find_attachment(
+    db: &DB,
+    note_id: Uuid,
+    attachment_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<(Attachment, i64)> {
+    db.call(move |conn| {
+        require_access(conn, note_id, user_id, Access::Read)?;
+        conn.query_row(
+            r#"SELECT id, note_id, file_name, content_type, size, checksum, created_at, created_by, rowid
+            FROM attachments
+            WHERE id = ? AND note_id = ? AND checksum IS NOT NULL"#,
+            params![attachment_id, note_id],
+            |row| Ok((Attachment::try_from(row)?, row.get(8)?)),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Attachment not found"))
+    .map_err(Error::from)
+}
+
+pub async fn get_attachment(
+    note_id: Uuid,
+    attachment_id: Uuid,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Attachment> {
+    let (attachment, _) = find_attachment(&db, note_id, attachment_id, ctx.get_user_id()).await?;
+    Ok(attachment)
+}
+
+/// Streams the content a chunk at a time through incremental blob I/O.
+pub async fn download_attachment(
+    note_id: Uuid,
+    attachment_id: Uuid,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<(Attachment, impl Stream<Item = Result<Bytes>>)> {
+    let (attachment, rowid) = find_attachment(&db, note_id, attachment_id, ctx.get_user_id()).await?;
+    let size = attachment.size;
+
+    let content = stream::try_unfold(0, move |offset| {
+        let db = db.clone();
+        async move {
+            if offset >= size {
+                return Ok(None);
+            }
+            let len = CHUNK_SIZE.min(size - offset) as usize;
+            let chunk = db
+                .call(move |conn| {
+                    let blob = conn.blob_open(DatabaseName::Main, "attachments", "data", rowid, true)?;
+                    let mut chunk = vec![0; len];
+                    blob.read_at_exact(&mut chunk, offset as usize)?;
+                    Ok(chunk)
+                })
+                .await
+                .map_err(db::Error::from)?;
+            Ok(Some((Bytes::from(chunk), offset + len as u64)))
+        }
+    });
+
+    Ok((attachment, content))
+}
+
+/// Reads a `size` field, then streams the `file` field into a blob allocated at that size.
+/// Failed uploads are removed, incomplete ones are never listed and purged once abandoned.
+pub async fn upload_attachment(
+    note_id: Uuid,
+    mut multipart: Multipart,
+    max_bytes: u64,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Attachment> {
+    let size = match multipart.next_field().await? {
+        Some(field) if field.name() == Some("size") => field
+            .text()
+            .await?
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| Error::Validation("Invalid size".into()))?,
+        _ => return Err(Error::Validation("Expected the size field first".into())),
+    };
+    if size > max_bytes {
+        return Err(Error::PayloadTooLarge(format!(
+            "Attachments are limited to {max_bytes} bytes"
+        )));
+    }
+
+    let Some(mut field) = multipart
+        .next_field()
+        .await?
+        .filter(|field| field.name() == Some("file"))
+    else {
+        return Err(Error::Validation("Expected the file field after size".into()));
+    };
+    let file_name = file_name(field.file_name());
+    let content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+    let user_id = ctx.get_user_id();
+
+    let rowid = db
+        .call(move |conn| {
+            require_access(conn, note_id, user_id, Access::Write)?;
+            conn.query_row(
+                r#"INSERT INTO attachments (note_id, file_name, content_type, size, created_by, data)
+                VALUES (?, ?, ?, ?, ?, zeroblob(?4))
+                RETURNING rowid"#,
+                params![note_id, file_name, content_type, size, user_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(|e| e.into())
+        })
+        .await
+        .map_err(db::Error::from)
+        .map_err(Error::from)?;
+
+    let checksum = match write_content(&db, rowid, size, &mut field).await {
+        Ok(checksum) => checksum,
+        Err(error) => {
+            db.call(move |conn| Ok(conn.execute("DELETE FROM attachments WHERE rowid = ?", params![rowid])?))
+                .await
+                .map_err(db::Error::from)?;
+            return Err(error);
+        }
+    };
+
+    db.call(move |conn| {
+        conn.query_row(
+            r#"UPDATE attachments SET checksum = ?
+            WHERE rowid = ?
+            RETURNING id, note_id, file_name, content_type, size, checksum, created_at, created_by"#,
+            params![checksum, rowid],
+            |row| Attachment::try_from(row),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Deletes uploads that never completed, started more than `max_age` ago, returns how many.
+/// These are left behind when the process stops in the middle of an upload.
+pub async fn purge_incomplete(db: &DB, max_age: std::time::Duration) -> Result<usize> {
+    let modifier = format!("-{} seconds", max_age.as_secs());
+    db.call(move |conn| {
+        conn.execute(
+            "DELETE FROM attachments WHERE checksum IS NULL AND created_at < datetime('now', ?)",
+            params![modifier],
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Writes the chunks of `field` at their offset in the blob, returns the hex encoded SHA-256.
+async fn write_content(db: &DB, rowid: i64, size: u64, field: &mut Field<'_>) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+
+    while let Some(chunk) = field.chunk().await? {
+        if offset + chunk.len() as u64 > size {
+            return Err(Error::Validation("File is larger than size".into()));
+        }
+        hasher.update(&chunk);
+
+        let len = chunk.len() as u64;
+        db.call(move |conn| {
+            let mut blob = conn.blob_open(DatabaseName::Main, "attachments", "data", rowid, false)?;
+            blob.write_all_at(&chunk, offset as usize)?;
+            Ok(())
+        })
+        .await
+        .map_err(db::Error::from)?;
+        offset += len;
+    }
+
+    if offset != size {
+        return Err(Error::Validation("File is smaller than size".into()));
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Base name of the uploaded file, safe to quote in `Content-Disposition`.
+fn file_name(name: Option<&str>) -> String {
+    name.and_then(|name| name.rsplit(['/', '\\']).next())
+        .map(|name| {
+            name.chars()
+                .filter(|c| !c.is_control() && *c != '"')
+                .collect::<String>()
+        })
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "attachment".into())
+}
+
+pub async fn delete_attachment(
+    note_id: Uuid,
+    attachment_id: Uuid,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Attachment> {
+    db.call(move |conn| {
+        require_access(conn, note_id, ctx.get_user_id(), Access::Write)?;
+        conn.query_row(
+            r#"DELETE FROM attachments
+            WHERE id = ? AND note_id = ? AND checksum IS NOT NULL
+            RETURNING id, note_id, file_name, content_type, size, checksum, created_at, created_by"#,
+            params![attachment_id, note_id],
+            |row| Attachment::try_from(row),
+        )
+        .optional()?
+        .ok_or_else(|| Error::NotFound("Attachment not found".into()).into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
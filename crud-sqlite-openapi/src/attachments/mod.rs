@@ -0,0 +1,31 @@
+mod handlers;
+mod model;
+mod routes;
+
+use std::time::Duration;
+
+use model::*;
+
+use crate::{openapi::aide::axum::ApiRouter, state::AppState, DB};
+
+/// Uploads still incomplete after this long are considered abandoned.
+const INCOMPLETE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new().merge(routes::router(state.clone()))
+}
+
+/// Purges abandoned uploads every hour, starting right away.
+pub fn spawn_upload_purge(db: DB) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match handlers::purge_incomplete(&db, INCOMPLETE_MAX_AGE).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged incomplete uploads"),
+                Err(error) => tracing::error!(?error, "failed to purge incomplete uploads"),
+            }
+        }
+    })
+}
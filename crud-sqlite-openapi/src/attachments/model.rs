@@ -0,0 +1,46 @@
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    /// In bytes.
+    pub size: u64,
+    /// Hex encoded SHA-256 of the content.
+    pub checksum: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindAttachmentsResponse {
+    /// Oldest first.
+    pub results: Vec<Attachment>,
+}
+
+/// Upload form. The file is streamed to storage, so its size has to be known first.
+#[derive(JsonSchema)]
+#[allow(dead_code)] // only documents the form, fields are read from the stream
+pub struct UploadAttachment {
+    /// Size of `file` in bytes. Must come before `file`.
+    pub size: u64,
+    #[schemars(schema_with = "binary")]
+    pub file: Vec<u8>,
+}
+
+fn binary(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some("binary".into()),
+        ..Default::default()
+    }
+    .into()
+}
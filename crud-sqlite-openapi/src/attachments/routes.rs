@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use crate::{
+    config::Config,
+    openapi::{
+        aide::axum::{routing::get_with, ApiRouter, IntoApiResponse},
+        Json, Multipart, Path, TransformOperationExt,
+    },
+    policy::{
+        policies::{NotesRead, NotesWrite},
+        Authorized,
+    },
+    state::AppState,
+    Result,
+};
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{handlers, Attachment, UploadAttachment};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NoteIdPath {
+    note_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct AttachmentPath {
+    note_id: Uuid,
+    attachment_id: Uuid,
+}
+
+/// Room for the multipart boundaries and headers around the file.
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+pub fn router(state: AppState) -> ApiRouter {
+    let body_limit = state.config.attachment_max_bytes as usize + MULTIPART_OVERHEAD;
+
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/notes/{note_id}/attachments",
+            get_with(find_attachments, |t| {
+                t.description("Files attached to the note.")
+                    .error::<404>("Note not found")
+            })
+            .post_with(upload_attachment, |t| {
+                t.description("Attaches a file to the note. Send `size` before `file`, files are streamed to storage.")
+                    .response::<201, Json<Attachment>>()
+                    .error::<400>("Missing fields, or the file doesn't match `size`")
+                    .error::<403>("Read only access")
+                    .error::<404>("Note not found")
+                    .error::<413>("File too large")
+            })
+            .layer(DefaultBodyLimit::max(body_limit)),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/attachments/{attachment_id}",
+            get_with(get_attachment, |t| t.error::<404>("Note or attachment not found")).delete_with(
+                delete_attachment,
+                |t| {
+                    t.error::<403>("Read only access")
+                        .error::<404>("Note or attachment not found")
+                },
+            ),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/attachments/{attachment_id}/content",
+            get_with(download_attachment, |t| {
+                t.description("The file, with its content type.")
+                    .error::<404>("Note or attachment not found")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn find_attachments(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_attachments(note_id, base).await.map(Json)
+}
+
+async fn upload_attachment(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    State(config): State<Arc<Config>>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Multipart(multipart, _): Multipart<UploadAttachment>,
+) -> impl IntoApiResponse {
+    handlers::upload_attachment(note_id, multipart, config.attachment_max_bytes, base)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+async fn get_attachment(
+    Path(AttachmentPath { note_id, attachment_id }): Path<AttachmentPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::get_attachment(note_id, attachment_id, base).await.map(Json)
+}
+
+async fn download_attachment(
+    Path(AttachmentPath { note_id, attachment_id }): Path<AttachmentPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> Result<Response> {
+    let (attachment, content) = handlers::download_attachment(note_id, attachment_id, base).await?;
+
+    let content_type =
+        HeaderValue::from_str(&attachment.content_type).unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", attachment.file_name))
+        .unwrap_or(HeaderValue::from_static("attachment"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_LENGTH, attachment.size.into()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        ],
+        Body::from_stream(content),
+    )
+        .into_response())
+}
+
+async fn delete_attachment(
+    Path(AttachmentPath { note_id, attachment_id }): Path<AttachmentPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+) -> impl IntoApiResponse {
+    handlers::delete_attachment(note_id, attachment_id, base)
+        .await
+        .map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sha2::{Digest, Sha256};
+
+    use crate::{
+        attachments::{Attachment, FindAttachmentsResponse},
+        db::{init_test_db, DB},
+        errors::Result,
+        tests::authenticate,
+    };
+    use uuid::{uuid, Uuid};
+
+    const OTHER_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000002");
+    const URL: &str = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4/attachments";
+
+    fn form(size: usize, content: &[u8]) -> MultipartForm {
+        MultipartForm::new().add_text("size", size.to_string()).add_part(
+            "file",
+            Part::bytes(content.to_vec())
+                .file_name("../reports/final.pdf")
+                .mime_type("application/pdf"),
+        )
+    }
+
+    #[tokio::test]
+    async fn upload_and_download() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        // spans several download chunks
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let response = server.post(URL).multipart(form(content.len(), &content)).await;
+        assert_eq!(response.status_code(), 201);
+        let attachment = response.json::<Attachment>();
+        assert_eq!(attachment.file_name, "final.pdf");
+        assert_eq!(attachment.size, content.len() as u64);
+        assert_eq!(attachment.checksum, hex::encode(Sha256::digest(&content)));
+
+        let response = server.get(&format!("{URL}/{}/content", attachment.id)).await;
+        assert_eq!(response.header("content-type"), "application/pdf");
+        assert_eq!(response.as_bytes().as_ref(), content.as_slice());
+
+        let mut other = test_server(db.clone()).await?;
+        authenticate(&db, &mut other, OTHER_USER_ID).await?;
+        let response = other
+            .get(&format!("{URL}/{}/content", attachment.id))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 404);
+
+        server.delete(&format!("{URL}/{}", attachment.id)).await;
+        assert!(server
+            .get(URL)
+            .await
+            .json::<FindAttachmentsResponse>()
+            .results
+            .is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_uploads() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        for size in [3, 5] {
+            let response = server.post(URL).multipart(form(size, b"four")).expect_failure().await;
+            assert_eq!(response.status_code(), 400);
+        }
+
+        let too_large = crate::tests::test_config().attachment_max_bytes as usize + 1;
+        let response = server.post(URL).multipart(form(too_large, b"")).expect_failure().await;
+        assert_eq!(response.status_code(), 413);
+
+        let response = server
+            .post(URL)
+            .multipart(MultipartForm::new().add_part("file", Part::bytes(b"four".to_vec())))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 400);
+
+        // failed uploads leave nothing behind
+        let count = db
+            .call(|conn| Ok(conn.query_row::<u32, _, _>("SELECT count(*) FROM attachments", [], |r| r.get(0))?))
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn purges_incomplete_uploads() -> Result<()> {
+        let db = test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO attachments (note_id, file_name, content_type, size, created_at, data)
+                VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'abandoned', 'text/plain', 4, datetime('now', '-2 days'), zeroblob(4));
+                INSERT INTO attachments (note_id, file_name, content_type, size, data)
+                VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'uploading', 'text/plain', 4, zeroblob(4));
+                INSERT INTO attachments (note_id, file_name, content_type, size, checksum, created_at, data)
+                VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'complete', 'text/plain', 4, 'x', datetime('now', '-2 days'), zeroblob(4));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let purged = super::handlers::purge_incomplete(&db, Duration::from_secs(60 * 60)).await?;
+        assert_eq!(purged, 1);
+        let names = db
+            .call(|conn| {
+                Ok(conn
+                    .prepare("SELECT file_name FROM attachments ORDER BY file_name")?
+                    .query_map([], |r| r.get::<_, String>(0))?
+                    .collect::<std::result::Result<Vec<_>, _>>()?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(names, ["complete", "uploading"]);
+        Ok(())
+    }
+
+    async fn test_db() -> Result<DB> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        Ok(db)
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, super::router).await
+    }
+}
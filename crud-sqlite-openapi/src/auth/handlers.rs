@@ -0,0 +1,528 @@
+use std::str::FromStr;
+
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef},
+    OptionalExtension, Row, ToSql,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::RegistrationMode,
+    ctx::{BaseParams, Ctx, Role, User, UserStatus},
+    db,
+    policy::Permission,
+    Error, Result, DB,
+};
+
+use super::{
+    password, token, ApiToken, CreateApiToken, CreatedApiToken, FindApiTokensResponse, FindSessionsResponse, Login,
+    LoginResponse, Register, Session, API_TOKEN_PREFIX,
+};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// How often a session's expiry slides at most.
+const SESSION_TOUCH_SECONDS: i64 = 60;
+
+impl<'a> TryFrom<&Row<'a>> for User {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            email: row.get(1)?,
+            role: row.get(2)?,
+            status: row.get(3)?,
+        })
+    }
+}
+
+impl<'a> TryFrom<&Row<'a>> for ApiToken {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            scopes: parse_scopes(&row.get::<_, String>(2)?)?,
+            expires_at: row.get(3)?,
+            last_used_at: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+}
+
+impl Session {
+    fn from_row(row: &Row<'_>, current: Option<Uuid>) -> rusqlite::Result<Self> {
+        let id = row.get(0)?;
+        Ok(Self {
+            id,
+            user_agent: row.get(1)?,
+            created_at: row.get(2)?,
+            last_seen_at: row.get(3)?,
+            expires_at: row.get(4)?,
+            current: current == Some(id),
+        })
+    }
+}
+
+fn parse_scopes(scopes: &str) -> rusqlite::Result<Vec<Permission>> {
+    scopes
+        .split_whitespace()
+        .map(|scope| {
+            Permission::from_str(scope).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, e.into()))
+        })
+        .collect()
+}
+
+impl FromSql for Role {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Role::from_str(value.as_str()?).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for Role {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for UserStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        UserStatus::from_str(value.as_str()?).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for UserStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+/// Resolves a session token and slides its expiry, at most once per [`SESSION_TOUCH_SECONDS`] so that
+/// requests in a row don't each write. Expired sessions are ignored.
+/// Returns the user and the id of the session.
+pub async fn find_user_by_token(db: &DB, token: &str, ttl_seconds: i64) -> Result<Option<(User, Uuid)>> {
+    let token_hash = token::hash(token);
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::seconds(ttl_seconds);
+    let touched_before = now - chrono::Duration::seconds(SESSION_TOUCH_SECONDS);
+
+    db.call(move |conn| {
+        let found = conn
+            .query_row(
+                "SELECT id, user_id, last_seen_at FROM sessions WHERE token_hash = ? AND expires_at > ?",
+                params![token_hash, now],
+                |row| {
+                    Ok((
+                        row.get::<_, Uuid>(0)?,
+                        row.get::<_, Uuid>(1)?,
+                        row.get::<_, Option<chrono::DateTime<chrono::Utc>>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((session_id, user_id, last_seen_at)) = found else {
+            return Ok(None);
+        };
+        if last_seen_at.is_none_or(|last_seen_at| last_seen_at < touched_before) {
+            conn.execute(
+                "UPDATE sessions SET last_seen_at = ?, expires_at = ? WHERE id = ?",
+                params![now, expires_at, session_id],
+            )?;
+        }
+
+        let user = conn.query_row(
+            "SELECT id, email, role, status FROM users WHERE id = ?",
+            params![user_id],
+            |row| User::try_from(row),
+        )?;
+        Ok(Some((user, session_id)))
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Opens a session for `user_id` and returns its token. Expired sessions of the user are dropped.
+pub async fn create_session(db: &DB, user_id: Uuid, user_agent: Option<String>, ttl_seconds: i64) -> Result<String> {
+    let token = token::generate();
+    let token_hash = token::hash(&token);
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::seconds(ttl_seconds);
+
+    db.call(move |conn| {
+        conn.execute(
+            "DELETE FROM sessions WHERE user_id = ? AND expires_at <= ?",
+            params![user_id, now],
+        )?;
+        conn.execute(
+            "INSERT INTO sessions (user_id, token_hash, user_agent, last_seen_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+            params![user_id, token_hash, user_agent, now, expires_at],
+        )?;
+        Ok(())
+    })
+    .await
+    .map_err(db::Error::from)?;
+
+    Ok(token)
+}
+
+/// Ends every session of `user_id`, who has to log in again.
+/// Called when the user's privileges change, so that they apply to the very next request.
+pub fn end_user_sessions(conn: &rusqlite::Connection, user_id: Uuid) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM sessions WHERE user_id = ?", params![user_id])?;
+    Ok(())
+}
+
+/// Ends a session, e.g. on logout or when it is replaced by a new login.
+pub async fn end_session(db: &DB, session_id: Uuid) -> Result<()> {
+    db.call(move |conn| {
+        conn.execute("DELETE FROM sessions WHERE id = ?", params![session_id])?;
+        Ok(())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Resolves an API token, recording its use. Expired tokens are ignored.
+pub async fn find_user_by_api_token(db: &DB, token: &str) -> Result<Option<(User, Vec<Permission>)>> {
+    let token_hash = token::hash(token);
+    db.call(move |conn| {
+        let found = conn
+            .query_row(
+                r#"UPDATE api_tokens SET last_used_at = ?1
+                WHERE token_hash = ?2 AND (expires_at IS NULL OR expires_at > ?1)
+                RETURNING user_id, scopes"#,
+                params![chrono::Utc::now(), token_hash],
+                |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        let Some((user_id, scopes)) = found else {
+            return Ok(None);
+        };
+
+        let user = conn.query_row(
+            "SELECT id, email, role, status FROM users WHERE id = ?",
+            params![user_id],
+            |row| User::try_from(row),
+        )?;
+        Ok(Some((user, parse_scopes(&scopes)?)))
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Drops every session, API token and refresh token of `user_id`, signing the user out everywhere.
+pub fn revoke_user_credentials(conn: &rusqlite::Connection, user_id: Uuid) -> rusqlite::Result<()> {
+    end_user_sessions(conn, user_id)?;
+    conn.execute("DELETE FROM api_tokens WHERE user_id = ?", params![user_id])?;
+    conn.execute("DELETE FROM refresh_tokens WHERE user_id = ?", params![user_id])?;
+    Ok(())
+}
+
+pub async fn register(
+    Register { email, password }: Register,
+    mode: RegistrationMode,
+    whitelist: &[String],
+    db: DB,
+) -> Result<User> {
+    let email = normalize_email(&email)?;
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(Error::Validation(format!(
+            "Password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        )));
+    }
+
+    let status = registration_status(&email, mode, whitelist)?;
+
+    let password = password::hash(password).await?;
+
+    db.call(move |conn| {
+        // the unique email is the check, so that concurrent sign ups can't both pass it
+        match conn.query_row(
+            r#"INSERT INTO users (email, status, password) VALUES (?, ?, ?)
+            RETURNING id, email, role, status"#,
+            params![email, status, password],
+            |row| User::try_from(row),
+        ) {
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE => {
+                Err(Error::Conflict("Email is already registered".into()).into())
+            }
+            result => result.map_err(|e| e.into()),
+        }
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Status of a new account under the registration `mode`.
+pub fn registration_status(email: &str, mode: RegistrationMode, whitelist: &[String]) -> Result<UserStatus> {
+    match mode {
+        RegistrationMode::Free => Ok(UserStatus::Active),
+        RegistrationMode::WhiteList => {
+            if !whitelist
+                .iter()
+                .any(|allowed| allowed.trim().eq_ignore_ascii_case(email))
+            {
+                return Err(Error::RegistrationClosed);
+            }
+            Ok(UserStatus::Active)
+        }
+        RegistrationMode::WaitList => Ok(UserStatus::Pending),
+    }
+}
+
+pub async fn login(args: Login, user_agent: Option<String>, session_ttl_seconds: i64, db: DB) -> Result<LoginResponse> {
+    let user = verify_credentials(args, &db).await?;
+    let token = create_session(&db, user.id, user_agent, session_ttl_seconds).await?;
+    Ok(LoginResponse { token, user })
+}
+
+/// Returns the user with these credentials, provided they are active.
+pub async fn verify_credentials(Login { email, password }: Login, db: &DB) -> Result<User> {
+    let email = email.trim().to_lowercase();
+
+    let found = db
+        .call(move |conn| {
+            conn.query_row(
+                "SELECT id, email, role, status, password FROM users WHERE email = ?",
+                params![email],
+                |row| Ok((User::try_from(row)?, row.get::<_, Option<String>>(4)?)),
+            )
+            .optional()
+            .map_err(|e| e.into())
+        })
+        .await
+        .map_err(db::Error::from)?;
+
+    let Some((user, Some(hash))) = found else {
+        // takes as long as a wrong password, so that timing doesn't tell which accounts exist
+        password::verify_dummy(password).await?;
+        return Err(Error::InvalidCredentials);
+    };
+    if !password::verify(password, hash).await? {
+        return Err(Error::InvalidCredentials);
+    }
+    if user.status != UserStatus::Active {
+        return Err(Error::Forbidden);
+    }
+    Ok(user)
+}
+
+pub async fn create_api_token(
+    CreateApiToken {
+        name,
+        scopes,
+        expires_at,
+    }: CreateApiToken,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<CreatedApiToken> {
+    let user_id = require_session(&ctx)?;
+    let role = ctx.user.as_ref().map(|u| u.role).ok_or(Error::Unauthorized)?;
+
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::Validation("Token name is required".into()));
+    }
+    if scopes.is_empty() {
+        return Err(Error::Validation("At least one scope is required".into()));
+    }
+    if let Some(scope) = scopes.iter().find(|scope| !role.permissions().contains(scope)) {
+        return Err(Error::Validation(format!(
+            "Scope {} is not granted to your role",
+            scope.as_str()
+        )));
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return Err(Error::Validation("Expiry must be in the future".into()));
+    }
+
+    let mut unique = Vec::new();
+    for scope in scopes {
+        if !unique.contains(&scope) {
+            unique.push(scope);
+        }
+    }
+    let scopes = unique.iter().map(Permission::as_str).collect::<Vec<_>>().join(" ");
+
+    let secret = format!("{API_TOKEN_PREFIX}{}", token::generate());
+    let token_hash = token::hash(&secret);
+
+    let token = db
+        .call(move |conn| {
+            conn.query_row(
+                r#"INSERT INTO api_tokens (user_id, name, token_hash, scopes, expires_at) VALUES (?, ?, ?, ?, ?)
+                RETURNING id, name, scopes, expires_at, last_used_at, created_at"#,
+                params![user_id, name, token_hash, scopes, expires_at],
+                |row| ApiToken::try_from(row),
+            )
+            .map_err(|e| e.into())
+        })
+        .await
+        .map_err(db::Error::from)?;
+
+    Ok(CreatedApiToken { secret, token })
+}
+
+pub async fn find_api_tokens(BaseParams { db, ctx }: BaseParams) -> Result<FindApiTokensResponse> {
+    let user_id = require_session(&ctx)?;
+
+    db.call(move |conn| {
+        let tokens = conn
+            .prepare(
+                r#"SELECT id, name, scopes, expires_at, last_used_at, created_at FROM api_tokens
+                WHERE user_id = ? ORDER BY id"#,
+            )?
+            .query_map(params![user_id], |row| ApiToken::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindApiTokensResponse { results: tokens })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn revoke_api_token(token_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<ApiToken> {
+    let user_id = require_session(&ctx)?;
+
+    db.call(move |conn| {
+        conn.query_row(
+            r#"DELETE FROM api_tokens WHERE id = ? AND user_id = ?
+            RETURNING id, name, scopes, expires_at, last_used_at, created_at"#,
+            params![token_id, user_id],
+            |row| ApiToken::try_from(row),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "API token not found"))
+    .map_err(Error::from)
+}
+
+pub async fn find_sessions(BaseParams { db, ctx }: BaseParams) -> Result<FindSessionsResponse> {
+    let user_id = require_session(&ctx)?;
+    let current = ctx.session_id;
+
+    db.call(move |conn| {
+        let sessions = conn
+            .prepare(
+                r#"SELECT id, user_agent, created_at, last_seen_at, expires_at FROM sessions
+                WHERE user_id = ? AND expires_at > ? ORDER BY last_seen_at DESC"#,
+            )?
+            .query_map(params![user_id, chrono::Utc::now()], |row| {
+                Session::from_row(row, current)
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindSessionsResponse { results: sessions })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Ends one of the caller's sessions, possibly the current one.
+pub async fn revoke_session(session_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Session> {
+    let user_id = require_session(&ctx)?;
+    let current = ctx.session_id;
+
+    db.call(move |conn| {
+        conn.query_row(
+            r#"DELETE FROM sessions WHERE id = ? AND user_id = ?
+            RETURNING id, user_agent, created_at, last_seen_at, expires_at"#,
+            params![session_id, user_id],
+            |row| Session::from_row(row, current),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Session not found"))
+    .map_err(Error::from)
+}
+
+/// API tokens can't be used to manage API tokens or sessions.
+fn require_session(ctx: &Ctx) -> Result<Uuid> {
+    if ctx.scopes.is_some() {
+        return Err(Error::Forbidden);
+    }
+    ctx.get_user_id().ok_or(Error::Unauthorized)
+}
+
+pub fn normalize_email(email: &str) -> Result<String> {
+    let email = email.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((name, domain)) if !name.is_empty() && !domain.is_empty() => Ok(email),
+        _ => Err(Error::Validation("Invalid email".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::init_test_db;
+
+    fn register_args(email: &str) -> Register {
+        Register {
+            email: email.into(),
+            password: "correct horse".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn white_list_registration() -> Result<()> {
+        let db = init_test_db().await?;
+        let whitelist = vec!["invited@mail.com".to_string()];
+
+        let user = register(
+            register_args("Invited@mail.com"),
+            RegistrationMode::WhiteList,
+            &whitelist,
+            db.clone(),
+        )
+        .await?;
+        assert_eq!(user.email, "invited@mail.com");
+        assert_eq!(user.status, UserStatus::Active);
+
+        let result = register(
+            register_args("stranger@mail.com"),
+            RegistrationMode::WhiteList,
+            &whitelist,
+            db,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::RegistrationClosed)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wait_list_registration() -> Result<()> {
+        let db = init_test_db().await?;
+
+        let user = register(
+            register_args("new@mail.com"),
+            RegistrationMode::WaitList,
+            &[],
+            db.clone(),
+        )
+        .await?;
+        assert_eq!(user.status, UserStatus::Pending);
+
+        let result = login(
+            Login {
+                email: "new@mail.com".into(),
+                password: "correct horse".into(),
+            },
+            None,
+            3600,
+            db,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Forbidden)));
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+//! Short-lived JWT access tokens for stateless services, renewed with one-time refresh tokens.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{pkcs8::EncodePrivateKey, SigningKey};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::{Config, JwtKey},
+    ctx::{Role, User, UserStatus},
+    db, Error, Result, DB,
+};
+
+use super::{token, verify_credentials, Jwk, Jwks, Login, TokenPair, TokenRequest};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: Uuid,
+    pub role: Role,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Keys parsed from [`crate::config::Config::jwt_keys`].
+pub struct Keys {
+    keys: Vec<Key>,
+}
+
+struct Key {
+    kid: String,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// Base64url encoded public key.
+    x: String,
+}
+
+impl Keys {
+    pub fn new(keys: &[JwtKey]) -> Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|JwtKey { kid, private_key }| {
+                let seed: [u8; 32] = URL_SAFE_NO_PAD
+                    .decode(private_key.trim())
+                    .ok()
+                    .and_then(|seed| seed.try_into().ok())
+                    .ok_or_else(|| {
+                        Error::Unexpected(format!("JWT key {kid} is not a base64url encoded 32 byte seed"))
+                    })?;
+
+                let signing_key = SigningKey::from_bytes(&seed);
+                let der = signing_key
+                    .to_pkcs8_der()
+                    .map_err(|e| Error::Unexpected(e.to_string()))?;
+                let x = URL_SAFE_NO_PAD.encode(signing_key.verifying_key().as_bytes());
+
+                Ok(Key {
+                    kid: kid.clone(),
+                    encoding: EncodingKey::from_ed_der(der.as_bytes()),
+                    decoding: DecodingKey::from_ed_components(&x).map_err(|e| Error::Unexpected(e.to_string()))?,
+                    x,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { keys })
+    }
+
+    /// Signs `claims` with the first key.
+    pub fn sign(&self, claims: &Claims) -> Result<String> {
+        let key = self
+            .keys
+            .first()
+            .ok_or_else(|| Error::Unexpected("No JWT keys configured".into()))?;
+
+        let header = Header {
+            kid: Some(key.kid.clone()),
+            ..Header::new(Algorithm::EdDSA)
+        };
+        jsonwebtoken::encode(&header, claims, &key.encoding).map_err(|e| Error::Unexpected(e.to_string()))
+    }
+
+    /// Returns the claims of a token signed by any of the keys, issued by `issuer` and not expired.
+    pub fn verify(&self, token: &str, issuer: &str) -> Option<Claims> {
+        let kid = jsonwebtoken::decode_header(token).ok()?.kid?;
+        let key = self.keys.iter().find(|key| key.kid == kid)?;
+
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_issuer(&[issuer]);
+        jsonwebtoken::decode::<Claims>(token, &key.decoding, &validation)
+            .ok()
+            .map(|data| data.claims)
+    }
+
+    pub fn jwks(&self) -> Jwks {
+        Jwks {
+            keys: self
+                .keys
+                .iter()
+                .map(|key| Jwk {
+                    kty: "OKP".into(),
+                    crv: "Ed25519".into(),
+                    alg: "EdDSA".into(),
+                    use_: "sig".into(),
+                    kid: key.kid.clone(),
+                    x: key.x.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// JWTs are told apart from the other bearer tokens by their three segments.
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// Resolves a verified JWT to its user.
+pub async fn find_user_by_jwt(db: &DB, keys: &Keys, issuer: &str, token: &str) -> Result<Option<User>> {
+    let Some(claims) = keys.verify(token, issuer) else {
+        return Ok(None);
+    };
+
+    db.call(move |conn| {
+        conn.query_row(
+            "SELECT id, email, role, status FROM users WHERE id = ?",
+            params![claims.sub],
+            |row| User::try_from(row),
+        )
+        .optional()
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Token endpoint: trades credentials or a refresh token for a new token pair.
+pub async fn token(request: TokenRequest, config: &Config, keys: &Keys, db: DB) -> Result<TokenPair> {
+    let refresh_ttl_seconds = config.jwt_refresh_ttl_seconds;
+    match request {
+        TokenRequest::Password { email, password } => {
+            let user = verify_credentials(Login { email, password }, &db).await?;
+            let refresh_token = token::generate();
+            let token_hash = token::hash(&refresh_token);
+            let user_id = user.id;
+
+            db.call(move |conn| {
+                insert_refresh_token(conn, user_id, Uuid::now_v7(), &token_hash, refresh_ttl_seconds)?;
+                Ok(())
+            })
+            .await
+            .map_err(db::Error::from)?;
+
+            token_pair(&user, refresh_token, config, keys)
+        }
+        TokenRequest::RefreshToken { refresh_token } => refresh(refresh_token, config, keys, db).await,
+    }
+}
+
+/// Trades a refresh token for a new pair. A refresh token that was already used means it leaked:
+/// the whole family, including the token that replaced it, is revoked.
+async fn refresh(refresh_token: String, config: &Config, keys: &Keys, db: DB) -> Result<TokenPair> {
+    let refresh_ttl_seconds = config.jwt_refresh_ttl_seconds;
+    let token_hash = token::hash(&refresh_token);
+    let new_token = token::generate();
+    let new_token_hash = token::hash(&new_token);
+
+    let user = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let now = chrono::Utc::now();
+
+            let found = tx
+                .query_row(
+                    "SELECT user_id, family_id, used_at FROM refresh_tokens WHERE token_hash = ? AND expires_at > ?",
+                    params![token_hash, now],
+                    |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            row.get::<_, Uuid>(1)?,
+                            row.get::<_, Option<chrono::DateTime<chrono::Utc>>>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((user_id, family_id, used_at)) = found else {
+                return Err(Error::InvalidCredentials.into());
+            };
+
+            if used_at.is_some() {
+                tracing::warn!(%user_id, %family_id, "refresh token reused, revoking its family");
+                tx.execute("DELETE FROM refresh_tokens WHERE family_id = ?", params![family_id])?;
+                tx.commit()?;
+                return Err(Error::InvalidCredentials.into());
+            }
+
+            let user = tx.query_row(
+                "SELECT id, email, role, status FROM users WHERE id = ?",
+                params![user_id],
+                |row| User::try_from(row),
+            )?;
+            if user.status != UserStatus::Active {
+                return Err(Error::Forbidden.into());
+            }
+
+            tx.execute(
+                "UPDATE refresh_tokens SET used_at = ? WHERE token_hash = ?",
+                params![now, token_hash],
+            )?;
+            insert_refresh_token(&tx, user_id, family_id, &new_token_hash, refresh_ttl_seconds)?;
+            tx.commit()?;
+            Ok(user)
+        })
+        .await
+        .map_err(db::Error::from)?;
+
+    token_pair(&user, new_token, config, keys)
+}
+
+fn insert_refresh_token(
+    conn: &rusqlite::Connection,
+    user_id: Uuid,
+    family_id: Uuid,
+    token_hash: &str,
+    ttl_seconds: i64,
+) -> rusqlite::Result<()> {
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl_seconds);
+    conn.execute(
+        "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) VALUES (?, ?, ?, ?)",
+        params![user_id, family_id, token_hash, expires_at],
+    )?;
+    Ok(())
+}
+
+fn token_pair(user: &User, refresh_token: String, config: &Config, keys: &Keys) -> Result<TokenPair> {
+    let iat = chrono::Utc::now().timestamp();
+    let access_token = keys.sign(&Claims {
+        iss: config.public_url.clone(),
+        sub: user.id,
+        role: user.role,
+        iat,
+        exp: iat + config.jwt_access_ttl_seconds,
+    })?;
+
+    Ok(TokenPair {
+        access_token,
+        token_type: "Bearer".into(),
+        expires_in: config.jwt_access_ttl_seconds,
+        refresh_token,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(kid: &str, seed: u8) -> JwtKey {
+        JwtKey {
+            kid: kid.into(),
+            private_key: URL_SAFE_NO_PAD.encode([seed; 32]),
+        }
+    }
+
+    fn claims(exp: i64) -> Claims {
+        Claims {
+            iss: "http://notes".into(),
+            sub: Uuid::now_v7(),
+            role: Role::Member,
+            iat: 0,
+            exp,
+        }
+    }
+
+    #[test]
+    fn key_rotation() -> Result<()> {
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let old = Keys::new(&[key("old", 1)])?;
+        let old_token = old.sign(&claims(exp))?;
+
+        let rotated = Keys::new(&[key("new", 2), key("old", 1)])?;
+        let new_token = rotated.sign(&claims(exp))?;
+        assert!(rotated.verify(&old_token, "http://notes").is_some());
+        assert!(rotated.verify(&new_token, "http://notes").is_some());
+        assert_eq!(rotated.jwks().keys.len(), 2);
+
+        let dropped = Keys::new(&[key("new", 2)])?;
+        assert!(dropped.verify(&old_token, "http://notes").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_tokens() -> Result<()> {
+        let keys = Keys::new(&[key("k", 1)])?;
+
+        let expired = keys.sign(&claims(chrono::Utc::now().timestamp() - 3600))?;
+        assert!(keys.verify(&expired, "http://notes").is_none());
+
+        let token = keys.sign(&claims(chrono::Utc::now().timestamp() + 60))?;
+        assert!(keys.verify(&token, "http://elsewhere").is_none());
+
+        // same kid, different key
+        let forged = Keys::new(&[key("k", 2)])?.sign(&claims(chrono::Utc::now().timestamp() + 60))?;
+        assert!(keys.verify(&forged, "http://notes").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+mod handlers;
+pub mod jwt;
+mod model;
+pub mod oauth;
+pub mod password;
+mod routes;
+pub mod token;
+
+pub use handlers::*;
+pub use model::*;
+
+use axum_extra::extract::cookie::{Cookie, SameSite};
+
+use crate::{openapi::aide::axum::ApiRouter, state::AppState};
+
+pub const SESSION_COOKIE: &str = "session";
+/// Tells API tokens apart from session tokens in `Authorization: Bearer`.
+pub const API_TOKEN_PREFIX: &str = "nat_";
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new().merge(routes::router(state.clone()))
+}
+
+/// Carries the session token for browsers, see [`crate::ctx::Ctx::resolve`].
+pub fn session_cookie(token: String, ttl_seconds: i64) -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(ttl_seconds))
+        .build()
+}
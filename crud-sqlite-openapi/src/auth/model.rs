@@ -0,0 +1,109 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use uuid::Uuid;
+
+use crate::{ctx::User, policy::Permission};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Register {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Login {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LoginResponse {
+    /// Session token, usable as `Authorization: Bearer <token>`.
+    pub token: String,
+    pub user: User,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateApiToken {
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    /// Never expires when omitted.
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CreatedApiToken {
+    /// Shown only once, usable as `Authorization: Bearer <secret>`.
+    pub secret: String,
+    #[serde(flatten)]
+    pub token: ApiToken,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindApiTokensResponse {
+    pub results: Vec<ApiToken>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_seen_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Pushed forward every time the session is used.
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// Whether this is the session of the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindSessionsResponse {
+    pub results: Vec<Session>,
+}
+
+/// Body of the token endpoint, modeled after the OAuth 2 grant types.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "grant_type", rename_all = "snake_case")]
+pub enum TokenRequest {
+    Password { email: String, password: String },
+    RefreshToken { refresh_token: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TokenPair {
+    /// JWT, usable as `Authorization: Bearer <access_token>`.
+    pub access_token: String,
+    pub token_type: String,
+    /// Lifetime of the access token in seconds.
+    pub expires_in: i64,
+    /// One-time use, exchanged for a new pair at the token endpoint.
+    pub refresh_token: String,
+}
+
+/// Public keys verifying access tokens, as a JSON Web Key Set.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub kid: String,
+    pub x: String,
+}
@@ -0,0 +1,664 @@
+//! OpenID Connect login with the authorization code flow and PKCE.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rusqlite::{params, OptionalExtension};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    config::{Config, OAuthProvider},
+    ctx::{User, UserStatus},
+    db, Error, Result, DB,
+};
+
+use super::{create_session, normalize_email, registration_status, token, LoginResponse};
+
+const STATE_TTL_SECONDS: i64 = 10 * 60;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OAuthCallback {
+    pub code: Option<String>,
+    pub state: String,
+    /// Set by the provider when the user denied access.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+/// Issuer, audience and expiry are checked by [`Validation`].
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserInfo {
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+/// Starts a login: stores the PKCE verifier and returns the provider's authorization URL.
+pub async fn authorize(provider: &OAuthProvider, redirect_uri: &str, db: DB) -> Result<String> {
+    let discovery = discover(provider).await?;
+
+    let state = token::generate();
+    let code_verifier = token::generate();
+    let nonce = token::generate();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    let mut url = Url::parse(&discovery.authorization_endpoint).map_err(|e| Error::OAuth(e.to_string()))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", &provider.scopes)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &code_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    let name = provider.name.clone();
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::seconds(STATE_TTL_SECONDS);
+    db.call(move |conn| {
+        // abandoned logins never reach the callback that deletes their state
+        conn.execute("DELETE FROM oauth_states WHERE expires_at <= ?", params![now])?;
+        conn.execute(
+            "INSERT INTO oauth_states (state, provider, code_verifier, nonce, expires_at) VALUES (?, ?, ?, ?, ?)",
+            params![state, name, code_verifier, nonce, expires_at],
+        )?;
+        Ok(())
+    })
+    .await
+    .map_err(db::Error::from)?;
+
+    Ok(url.into())
+}
+
+/// Finishes a login: exchanges the code, provisions the user by their verified email and opens a session.
+/// An existing account is only signed in when it was registered or linked with this provider,
+/// otherwise anyone controlling the email at the provider could take it over.
+pub async fn callback(
+    provider: &OAuthProvider,
+    OAuthCallback { code, state, error }: OAuthCallback,
+    redirect_uri: &str,
+    config: &Config,
+    user_agent: Option<String>,
+    db: DB,
+) -> Result<LoginResponse> {
+    let name = provider.name.clone();
+    let found = db
+        .call(move |conn| {
+            conn.query_row(
+                r#"DELETE FROM oauth_states WHERE state = ? AND provider = ? AND expires_at > ?
+                RETURNING code_verifier, nonce"#,
+                params![state, name, chrono::Utc::now()],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.into())
+        })
+        .await
+        .map_err(db::Error::from)?;
+    let Some((code_verifier, nonce)) = found else {
+        return Err(Error::OAuth("Unknown or expired state".into()));
+    };
+
+    if let Some(error) = error {
+        return Err(Error::OAuth(format!("Provider returned {error}")));
+    }
+    let code = code.ok_or_else(|| Error::OAuth("Missing code".into()))?;
+
+    let discovery = discover(provider).await?;
+    let client = reqwest::Client::new();
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", redirect_uri),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", code_verifier.as_str()),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+    let tokens: TokenResponse = fetch_json(client.post(&discovery.token_endpoint).form(&form)).await?;
+
+    let id_token = tokens
+        .id_token
+        .ok_or_else(|| Error::OAuth("Provider returned no id_token".into()))?;
+    let jwks: JwkSet = fetch_json(client.get(&discovery.jwks_uri)).await?;
+    verify_id_token(&id_token, &jwks, &discovery.issuer, &provider.client_id, &nonce)?;
+
+    let info: UserInfo = fetch_json(
+        client
+            .get(&discovery.userinfo_endpoint)
+            .bearer_auth(&tokens.access_token),
+    )
+    .await?;
+    let email = match (info.email, info.email_verified) {
+        (Some(email), Some(true)) => normalize_email(&email)?,
+        _ => return Err(Error::OAuth("Provider returned no verified email".into())),
+    };
+
+    let new_status = registration_status(&email, config.registration_mode, &config.registration_whitelist);
+    let name = provider.name.clone();
+    let access_token = tokens.access_token;
+    let user = db
+        .call(move |conn| {
+            let existing = conn
+                .query_row(
+                    "SELECT id, email, role, status, oauth_provider FROM users WHERE email = ?",
+                    params![email],
+                    |row| Ok((User::try_from(row)?, row.get::<_, Option<String>>(4)?)),
+                )
+                .optional()?;
+
+            let user = match existing {
+                Some((_, linked)) if linked.as_ref() != Some(&name) => {
+                    return Err(Error::Conflict(
+                        "An account with this email exists and is not linked to this provider".into(),
+                    )
+                    .into())
+                }
+                Some((user, _)) => {
+                    conn.execute(
+                        "UPDATE users SET oauth_provider = ?, access_token = ?, updated_at = ? WHERE id = ?",
+                        params![name, access_token, chrono::Utc::now(), user.id],
+                    )?;
+                    user
+                }
+                None => conn.query_row(
+                    r#"INSERT INTO users (email, status, oauth_provider, access_token) VALUES (?, ?, ?, ?)
+                    RETURNING id, email, role, status"#,
+                    params![email, new_status?, name, access_token],
+                    |row| User::try_from(row),
+                )?,
+            };
+            Ok(user)
+        })
+        .await
+        .map_err(db::Error::from)?;
+
+    if user.status != UserStatus::Active {
+        return Err(Error::Forbidden);
+    }
+
+    let token = create_session(&db, user.id, user_agent, config.session_ttl_seconds).await?;
+    Ok(LoginResponse { token, user })
+}
+
+async fn discover(provider: &OAuthProvider) -> Result<Discovery> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        provider.issuer.trim_end_matches('/')
+    );
+    let discovery: Discovery = fetch_json(reqwest::Client::new().get(url)).await?;
+
+    if discovery.issuer != provider.issuer {
+        return Err(Error::OAuth("Issuer mismatch in discovery document".into()));
+    }
+    Ok(discovery)
+}
+
+/// Client errors of the provider fail the login, an unreachable or broken provider is a bad gateway.
+async fn fetch_json<T: serde::de::DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await.map_err(|e| Error::OAuthProvider(e.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        let message = format!("Provider responded with {status} at {}", response.url().path());
+        return Err(if status.is_client_error() {
+            Error::OAuth(message)
+        } else {
+            Error::OAuthProvider(message)
+        });
+    }
+    response.json().await.map_err(|e| Error::OAuthProvider(e.to_string()))
+}
+
+/// Checks the signature of an id_token with the provider's keys, then its claims.
+/// Only asymmetric algorithms are accepted, `none` and HMAC would let anyone forge tokens.
+fn verify_id_token(id_token: &str, jwks: &JwkSet, issuer: &str, client_id: &str, nonce: &str) -> Result<()> {
+    let invalid = || Error::OAuth("Invalid id_token".into());
+
+    let header = jsonwebtoken::decode_header(id_token).map_err(|_| invalid())?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(invalid());
+    }
+    let jwk = match (&header.kid, jwks.keys.as_slice()) {
+        (Some(kid), _) => jwks.find(kid),
+        (None, [jwk]) => Some(jwk),
+        (None, _) => None,
+    }
+    .ok_or_else(invalid)?;
+    if jwk
+        .common
+        .key_algorithm
+        .is_some_and(|alg| alg.to_string().parse() != Ok(header.alg))
+    {
+        return Err(invalid());
+    }
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| invalid())?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[client_id]);
+    let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|_| invalid())?
+        .claims;
+
+    (claims.nonce.as_deref() == Some(nonce))
+        .then_some(())
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        extract::{Form, Query, State},
+        http::{header, HeaderMap, StatusCode},
+        response::{IntoResponse, Redirect},
+        routing::{get, post},
+        Json, Router,
+    };
+    use axum_test::{TestResponse, TestServer};
+    use ed25519_dalek::{pkcs8::EncodePrivateKey, SigningKey};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::RegistrationMode,
+        db::init_test_db,
+        tests::{test_config, test_server_with_config, TEST_USER_ID},
+    };
+
+    const CLIENT_ID: &str = "notes";
+    const CLIENT_SECRET: &str = "s3cret";
+    const MOCK_KEY: [u8; 32] = [7; 32];
+
+    /// How the mock provider signs its id_tokens.
+    #[derive(Clone, Copy)]
+    enum Signing {
+        Valid,
+        /// With a key that is not in its JWKS.
+        Forged,
+        /// With `alg: none`.
+        Unsigned,
+    }
+
+    /// Minimal OpenID provider: approves every authorization request as `email`.
+    #[derive(Clone)]
+    struct MockProvider {
+        issuer: String,
+        email: String,
+        signing: Signing,
+        grants: Arc<Mutex<HashMap<String, Grant>>>,
+    }
+
+    struct Grant {
+        code_challenge: String,
+        redirect_uri: String,
+        nonce: String,
+    }
+
+    async fn start_mock_provider(email: &str, signing: Signing) -> OAuthProvider {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let mock = MockProvider {
+            issuer: issuer.clone(),
+            email: email.into(),
+            signing,
+            grants: Default::default(),
+        };
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(mock_discovery))
+            .route("/jwks", get(mock_jwks))
+            .route("/authorize", get(mock_authorize))
+            .route("/token", post(mock_token))
+            .route("/userinfo", get(mock_userinfo))
+            .with_state(mock);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        OAuthProvider {
+            name: "mock".into(),
+            issuer,
+            client_id: CLIENT_ID.into(),
+            client_secret: Some(CLIENT_SECRET.into()),
+            scopes: "openid email".into(),
+        }
+    }
+
+    async fn mock_discovery(State(mock): State<MockProvider>) -> impl IntoResponse {
+        let issuer = &mock.issuer;
+        Json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "userinfo_endpoint": format!("{issuer}/userinfo"),
+            "jwks_uri": format!("{issuer}/jwks"),
+        }))
+    }
+
+    async fn mock_jwks() -> impl IntoResponse {
+        let x = URL_SAFE_NO_PAD.encode(SigningKey::from_bytes(&MOCK_KEY).verifying_key().as_bytes());
+        Json(json!({
+            "keys": [{ "kty": "OKP", "crv": "Ed25519", "alg": "EdDSA", "use": "sig", "kid": "mock", "x": x }],
+        }))
+    }
+
+    async fn mock_authorize(
+        State(mock): State<MockProvider>,
+        Query(params): Query<HashMap<String, String>>,
+    ) -> impl IntoResponse {
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        let code = token::generate();
+        mock.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                code_challenge: params["code_challenge"].clone(),
+                redirect_uri: params["redirect_uri"].clone(),
+                nonce: params["nonce"].clone(),
+            },
+        );
+        Redirect::to(&format!(
+            "{}?code={code}&state={}",
+            params["redirect_uri"], params["state"]
+        ))
+    }
+
+    async fn mock_token(
+        State(mock): State<MockProvider>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> impl IntoResponse {
+        let Some(grant) = mock.grants.lock().unwrap().remove(&form["code"]) else {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" })));
+        };
+
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+        if challenge != grant.code_challenge
+            || form["redirect_uri"] != grant.redirect_uri
+            || form.get("client_secret").map(String::as_str) != Some(CLIENT_SECRET)
+        {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" })));
+        }
+
+        let claims = json!({
+            "iss": mock.issuer,
+            "aud": CLIENT_ID,
+            "sub": "mock-subject",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": grant.nonce,
+        });
+        let id_token = match mock.signing {
+            Signing::Valid => sign(&claims, MOCK_KEY),
+            Signing::Forged => sign(&claims, [8; 32]),
+            Signing::Unsigned => format!(
+                "{}.{}.",
+                URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            ),
+        };
+        (
+            StatusCode::OK,
+            Json(json!({ "access_token": "mock-access-token", "token_type": "Bearer", "id_token": id_token })),
+        )
+    }
+
+    fn sign(claims: &serde_json::Value, seed: [u8; 32]) -> String {
+        let der = SigningKey::from_bytes(&seed).to_pkcs8_der().unwrap();
+        let header = Header {
+            kid: Some("mock".into()),
+            ..Header::new(Algorithm::EdDSA)
+        };
+        jsonwebtoken::encode(&header, claims, &EncodingKey::from_ed_der(der.as_bytes())).unwrap()
+    }
+
+    async fn mock_userinfo(State(mock): State<MockProvider>, headers: HeaderMap) -> impl IntoResponse {
+        if headers["authorization"] != "Bearer mock-access-token" {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Json(json!({ "sub": "mock-subject", "email": mock.email, "email_verified": true })).into_response()
+    }
+
+    /// Anonymous test server signing in with `provider`.
+    async fn test_server(provider: OAuthProvider, registration_mode: RegistrationMode) -> Result<(TestServer, DB)> {
+        let db = init_test_db().await?;
+        let config = Config {
+            oauth_providers: vec![provider],
+            registration_mode,
+            ..test_config()
+        };
+        let mut server = test_server_with_config(db.clone(), config, crate::auth::router).await?;
+        server.clear_headers();
+        Ok((server, db))
+    }
+
+    /// Starts a sign in and follows the redirects through the mock provider, like a browser would.
+    /// Returns the path and query of the callback.
+    async fn approve(server: &TestServer) -> String {
+        let response = server.get("/api/v1/auth/oauth/mock/authorize").expect_failure().await;
+        assert_eq!(response.status_code(), StatusCode::SEE_OTHER);
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = client
+            .get(response.header(header::LOCATION).to_str().unwrap())
+            .send()
+            .await
+            .unwrap();
+        let callback = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+        format!("{}?{}", callback.path(), callback.query().unwrap())
+    }
+
+    async fn sign_in(server: &TestServer) -> LoginResponse {
+        let callback = approve(server).await;
+        server.get(&callback).await.json()
+    }
+
+    async fn fail_sign_in(server: &TestServer) -> TestResponse {
+        let callback = approve(server).await;
+        server.get(&callback).expect_failure().await
+    }
+
+    async fn query_user(db: &DB, email: &'static str) -> (UserStatus, Option<String>, Option<String>) {
+        db.call(move |conn| {
+            conn.query_row(
+                "SELECT status, oauth_provider, access_token FROM users WHERE email = ?",
+                params![email],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| e.into())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn login_provisions_user() -> Result<()> {
+        let provider = start_mock_provider("Someone@mail.com", Signing::Valid).await;
+        let (server, db) = test_server(provider, RegistrationMode::Free).await?;
+
+        let login = sign_in(&server).await;
+        assert_eq!(login.user.email, "someone@mail.com");
+        assert_eq!(login.user.status, UserStatus::Active);
+        assert_eq!(
+            query_user(&db, "someone@mail.com").await,
+            (
+                UserStatus::Active,
+                Some("mock".into()),
+                Some("mock-access-token".into())
+            )
+        );
+
+        // signs in again with the linked account
+        assert_eq!(sign_in(&server).await.user.id, login.user.id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn links_only_accounts_of_the_provider() -> Result<()> {
+        let provider = start_mock_provider("fake@mail.com", Signing::Valid).await;
+        let (server, db) = test_server(provider, RegistrationMode::WhiteList).await?;
+
+        fail_sign_in(&server).await.assert_status(StatusCode::CONFLICT);
+        assert_eq!(query_user(&db, "fake@mail.com").await.1, None);
+
+        db.call(|conn| {
+            conn.execute(
+                "UPDATE users SET oauth_provider = 'mock' WHERE email = 'fake@mail.com'",
+                [],
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(sign_in(&server).await.user.id, TEST_USER_ID);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wait_list_provisions_pending_user() -> Result<()> {
+        let provider = start_mock_provider("someone@mail.com", Signing::Valid).await;
+        let (server, db) = test_server(provider, RegistrationMode::WaitList).await?;
+
+        fail_sign_in(&server).await.assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(query_user(&db, "someone@mail.com").await.0, UserStatus::Pending);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_unverified_id_tokens() -> Result<()> {
+        for signing in [Signing::Forged, Signing::Unsigned] {
+            let provider = start_mock_provider("someone@mail.com", signing).await;
+            let (server, db) = test_server(provider, RegistrationMode::Free).await?;
+
+            fail_sign_in(&server).await.assert_status_unauthorized();
+            let users = db
+                .call(|conn| {
+                    let count = conn.query_row(
+                        "SELECT count(*) FROM users WHERE email = 'someone@mail.com'",
+                        [],
+                        |row| row.get::<_, i64>(0),
+                    )?;
+                    Ok(count)
+                })
+                .await
+                .unwrap();
+            assert_eq!(users, 0);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_state() -> Result<()> {
+        let provider = start_mock_provider("someone@mail.com", Signing::Valid).await;
+        let (server, _) = test_server(provider, RegistrationMode::Free).await?;
+
+        let callback = approve(&server).await;
+        server.get(&callback).await;
+        server
+            .get(&callback)
+            .expect_failure()
+            .await
+            .assert_status_unauthorized();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_code_verifier() -> Result<()> {
+        let provider = start_mock_provider("someone@mail.com", Signing::Valid).await;
+        let (server, db) = test_server(provider, RegistrationMode::Free).await?;
+
+        let callback = approve(&server).await;
+        db.call(|conn| {
+            conn.execute("UPDATE oauth_states SET code_verifier = 'forged'", [])?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        server
+            .get(&callback)
+            .expect_failure()
+            .await
+            .assert_status_unauthorized();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unreachable_provider_is_a_bad_gateway() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let provider = OAuthProvider {
+            name: "mock".into(),
+            issuer,
+            client_id: CLIENT_ID.into(),
+            client_secret: None,
+            scopes: "openid email".into(),
+        };
+        let (server, _) = test_server(provider, RegistrationMode::Free).await?;
+
+        let response = server.get("/api/v1/auth/oauth/mock/authorize").expect_failure().await;
+        response.assert_status(StatusCode::BAD_GATEWAY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn purges_expired_states() -> Result<()> {
+        let provider = start_mock_provider("someone@mail.com", Signing::Valid).await;
+        let (server, db) = test_server(provider, RegistrationMode::Free).await?;
+
+        db.call(|conn| {
+            conn.execute(
+                r#"INSERT INTO oauth_states (state, provider, code_verifier, nonce, expires_at)
+                VALUES ('abandoned', 'mock', '', '', '2024-01-01T00:00:00Z')"#,
+                [],
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        approve(&server).await;
+
+        let states = db
+            .call(|conn| {
+                let states = conn
+                    .prepare("SELECT state FROM oauth_states")?
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(states)
+            })
+            .await
+            .unwrap();
+        assert_eq!(states.len(), 1);
+        assert_ne!(states[0], "abandoned");
+        Ok(())
+    }
+}
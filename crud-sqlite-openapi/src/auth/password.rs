@@ -0,0 +1,50 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+use std::sync::LazyLock;
+
+use crate::{Error, Result};
+
+/// Verified against when there is no hash to check, see [`verify_dummy`].
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"dummy password", &salt)
+        .expect("hash the dummy password")
+        .to_string()
+});
+
+/// Hashes `password` with Argon2id into a PHC string. Runs on the blocking pool.
+pub async fn hash(password: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| Error::Unexpected(e.to_string()))
+    })
+    .await
+    .map_err(|e| Error::Unexpected(e.to_string()))?
+}
+
+pub async fn verify(password: String, hash: String) -> Result<bool> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash).map_err(|e| Error::Unexpected(e.to_string()))?;
+        Ok(Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    })
+    .await
+    .map_err(|e| Error::Unexpected(e.to_string()))?
+}
+
+/// Spends as long as [`verify`] does, for an account without a password. Always `false`.
+pub async fn verify_dummy(password: String) -> Result<bool> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&DUMMY_HASH).map_err(|e| Error::Unexpected(e.to_string()))?;
+        let _ = Argon2::default().verify_password(password.as_bytes(), &hash);
+        Ok(false)
+    })
+    .await
+    .map_err(|e| Error::Unexpected(e.to_string()))?
+}
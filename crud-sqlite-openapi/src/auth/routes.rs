@@ -0,0 +1,669 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Redirect,
+    Extension,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    config::{Config, OAuthProvider},
+    ctx::{BaseParams, Ctx, User},
+    openapi::{
+        aide::{
+            axum::{
+                routing::{delete_with, get_with, post_with},
+                ApiRouter, IntoApiResponse,
+            },
+            NoApi,
+        },
+        Json, Path, Query, TransformOperationExt,
+    },
+    state::AppState,
+    Error, Result, DB,
+};
+
+use super::{
+    handlers, jwt, oauth, session_cookie, ApiToken, CreateApiToken, CreatedApiToken, Login, LoginResponse, Register,
+    Session, TokenPair, TokenRequest, SESSION_COOKIE,
+};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TokenIdPath {
+    token_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SessionIdPath {
+    session_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ProviderPath {
+    provider: String,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/auth/register",
+            post_with(register, |t| {
+                t.description(
+                    "Signs up with email and password. Depending on the registration mode the account is active \
+                    right away, limited to whitelisted emails, or `pending` until an admin approves it.",
+                )
+                .response::<201, Json<User>>()
+                .error::<400>("Invalid email or too short password")
+                .error::<403>("Email is not whitelisted")
+                .error::<409>("Email is already registered")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/login",
+            post_with(login, |t| {
+                t.description(
+                    "Opens a session. The token is returned in the body and set as the `session` cookie. \
+                    A session the caller already had is ended.",
+                )
+                .response::<200, Json<LoginResponse>>()
+                .error::<401>("Invalid email or password")
+                .error::<403>("Account is pending or blocked")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/token",
+            post_with(token, |t| {
+                t.description(
+                    "Issues a short-lived JWT access token and a one-time refresh token, for a password or a \
+                    refresh token. Reusing a refresh token revokes every token refreshed from the same login.",
+                )
+                .response::<200, Json<TokenPair>>()
+                .error::<401>("Invalid credentials or refresh token")
+                .error::<403>("Account is pending or blocked")
+            }),
+        )
+        .api_route(
+            "/.well-known/jwks.json",
+            get_with(jwks, |t| {
+                t.description("Public keys verifying access tokens, including keys being rotated out.")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/logout",
+            post_with(logout, |t| {
+                t.description("Ends the current session and clears the `session` cookie.")
+                    .response::<204, ()>()
+                    .error::<401>("Not authenticated")
+                    .error::<403>("Called with an API token")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/sessions",
+            get_with(find_sessions, |t| {
+                t.description("Lists the caller's active sessions.")
+                    .error::<401>("Not authenticated")
+                    .error::<403>("Called with an API token")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/sessions/{session_id}",
+            delete_with(revoke_session, |t| {
+                t.description("Ends one of the caller's sessions, signing out the device that uses it.")
+                    .error::<401>("Not authenticated")
+                    .error::<403>("Called with an API token")
+                    .error::<404>("Session not found")
+            }),
+        )
+        .api_route("/api/v1/auth/me", get_with(me, |t| t.error::<401>("Not authenticated")))
+        .api_route(
+            "/api/v1/auth/tokens",
+            get_with(find_api_tokens, |t| {
+                t.description("Lists the caller's API tokens.")
+                    .error::<401>("Not authenticated")
+                    .error::<403>("Called with an API token")
+            })
+            .post_with(create_api_token, |t| {
+                t.description(
+                    "Creates a personal API token limited to the given scopes. \
+                    The secret is returned only once.",
+                )
+                .response::<201, Json<CreatedApiToken>>()
+                .error::<400>("Missing name or scopes, scope not granted to the caller's role, or expiry in the past")
+                .error::<401>("Not authenticated")
+                .error::<403>("Called with an API token")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/tokens/{token_id}",
+            delete_with(revoke_api_token, |t| {
+                t.description("Revokes one of the caller's API tokens.")
+                    .error::<401>("Not authenticated")
+                    .error::<403>("Called with an API token")
+                    .error::<404>("API token not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/oauth/{provider}/authorize",
+            get_with(oauth_authorize, |t| {
+                t.description("Redirects to the OpenID provider to sign in with the authorization code flow and PKCE.")
+                    .response_with::<303, (), _>(|r| r.description("Redirect to the provider"))
+                    .error::<404>("Unknown provider")
+                    .error::<502>("Provider is unreachable")
+            }),
+        )
+        .api_route(
+            "/api/v1/auth/oauth/{provider}/callback",
+            get_with(oauth_callback, |t| {
+                t.description(
+                    "Completes the sign in started by `authorize` and opens a session. Users are matched by their \
+                    verified email; unknown emails are registered according to the registration mode.",
+                )
+                .response::<200, Json<LoginResponse>>()
+                .error::<401>("Invalid state or id_token, denied access or failed exchange with the provider")
+                .error::<403>("Email is not whitelisted, or the account is pending or blocked")
+                .error::<404>("Unknown provider")
+                .error::<409>("An account with the email exists and is not linked to the provider")
+                .error::<502>("Provider is unreachable")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn register(
+    Extension(db): Extension<DB>,
+    State(config): State<Arc<Config>>,
+    Json(args): Json<Register>,
+) -> impl IntoApiResponse {
+    handlers::register(args, config.registration_mode, &config.registration_whitelist, db)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+async fn login(
+    Extension(db): Extension<DB>,
+    Extension(ctx): Extension<Ctx>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(args): Json<Login>,
+) -> impl IntoApiResponse {
+    let login = handlers::login(args, user_agent(&headers), config.session_ttl_seconds, db.clone()).await?;
+    if let Some(session_id) = ctx.session_id {
+        handlers::end_session(&db, session_id).await?;
+    }
+    let cookie = session_cookie(login.token.clone(), config.session_ttl_seconds);
+    Result::Ok((jar.add(cookie), Json(login)))
+}
+
+async fn token(
+    Extension(db): Extension<DB>,
+    State(AppState { config, keys, .. }): State<AppState>,
+    Json(args): Json<TokenRequest>,
+) -> impl IntoApiResponse {
+    jwt::token(args, &config, &keys, db).await.map(Json)
+}
+
+async fn jwks(State(keys): State<Arc<jwt::Keys>>) -> impl IntoApiResponse {
+    Json(keys.jwks())
+}
+
+async fn logout(NoApi(BaseParams { db, ctx }): NoApi<BaseParams>, jar: CookieJar) -> impl IntoApiResponse {
+    let session_id = ctx.session_id.ok_or(Error::Forbidden)?;
+    handlers::end_session(&db, session_id).await?;
+    Result::Ok((
+        StatusCode::NO_CONTENT,
+        jar.remove(Cookie::build(SESSION_COOKIE).path("/")),
+    ))
+}
+
+async fn find_sessions(NoApi(base): NoApi<BaseParams>) -> impl IntoApiResponse {
+    handlers::find_sessions(base).await.map(Json)
+}
+
+async fn revoke_session(
+    Path(SessionIdPath { session_id }): Path<SessionIdPath>,
+    NoApi(base): NoApi<BaseParams>,
+) -> impl IntoApiResponse {
+    handlers::revoke_session(session_id, base).await.map(Json::<Session>)
+}
+
+async fn me(NoApi(BaseParams { ctx, .. }): NoApi<BaseParams>) -> impl IntoApiResponse {
+    Json(ctx.user)
+}
+
+async fn find_api_tokens(NoApi(base): NoApi<BaseParams>) -> impl IntoApiResponse {
+    handlers::find_api_tokens(base).await.map(Json)
+}
+
+async fn create_api_token(NoApi(base): NoApi<BaseParams>, Json(args): Json<CreateApiToken>) -> impl IntoApiResponse {
+    handlers::create_api_token(args, base)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+async fn revoke_api_token(
+    Path(TokenIdPath { token_id }): Path<TokenIdPath>,
+    NoApi(base): NoApi<BaseParams>,
+) -> impl IntoApiResponse {
+    handlers::revoke_api_token(token_id, base).await.map(Json::<ApiToken>)
+}
+
+async fn oauth_authorize(
+    Path(ProviderPath { provider }): Path<ProviderPath>,
+    Extension(db): Extension<DB>,
+    State(config): State<Arc<Config>>,
+) -> impl IntoApiResponse {
+    let provider = oauth_provider(&config, &provider)?;
+    oauth::authorize(provider, &oauth_redirect_uri(&config, provider), db)
+        .await
+        .map(|url| Redirect::to(&url))
+}
+
+async fn oauth_callback(
+    Path(ProviderPath { provider }): Path<ProviderPath>,
+    Extension(db): Extension<DB>,
+    Extension(ctx): Extension<Ctx>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Query(params): Query<oauth::OAuthCallback>,
+) -> impl IntoApiResponse {
+    let provider = oauth_provider(&config, &provider)?;
+    let redirect_uri = oauth_redirect_uri(&config, provider);
+    let login = oauth::callback(
+        provider,
+        params,
+        &redirect_uri,
+        &config,
+        user_agent(&headers),
+        db.clone(),
+    )
+    .await?;
+    if let Some(session_id) = ctx.session_id {
+        handlers::end_session(&db, session_id).await?;
+    }
+    let cookie = session_cookie(login.token.clone(), config.session_ttl_seconds);
+    Result::Ok((jar.add(cookie), Json(login)))
+}
+
+fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+fn oauth_provider<'a>(config: &'a Config, name: &str) -> Result<&'a OAuthProvider> {
+    config
+        .oauth_providers
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| Error::NotFound(format!("Unknown OAuth provider: {name}")))
+}
+
+fn oauth_redirect_uri(config: &Config, provider: &OAuthProvider) -> String {
+    format!(
+        "{}/api/v1/auth/oauth/{}/callback",
+        config.public_url.trim_end_matches('/'),
+        provider.name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderValue};
+    use axum_test::TestServer;
+    use rusqlite::params;
+    use serde_json::json;
+
+    use crate::{
+        auth::{CreatedApiToken, FindApiTokensResponse, FindSessionsResponse, LoginResponse, TokenPair},
+        ctx::{User, UserStatus},
+        db::{init_test_db, DB},
+        errors::Result,
+        tests::TEST_USER_ID,
+    };
+
+    #[tokio::test]
+    async fn register_and_login() -> Result<()> {
+        let db = init_test_db().await?;
+        let mut server = test_server(db).await?;
+        server.clear_headers();
+
+        let response = server
+            .post("/api/v1/auth/register")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.json::<User>().status, UserStatus::Active);
+
+        let response = server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.json::<LoginResponse>().user.email, "new@mail.com");
+        assert!(response.maybe_cookie("session").is_some());
+
+        // authenticated by the saved session cookie
+        let response = server.get("/api/v1/auth/me").await;
+        assert_eq!(response.json::<User>().email, "new@mail.com");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn register_twice() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db).await?;
+
+        let args = json!({ "email": "new@mail.com", "password": "correct horse" });
+        server.post("/api/v1/auth/register").json(&args).await;
+        let response = server.post("/api/v1/auth/register").json(&args).expect_failure().await;
+        assert_eq!(response.status_code(), 409);
+
+        let args = json!({ "email": "NEW@mail.com", "password": "correct horse" });
+        let response = server.post("/api/v1/auth/register").json(&args).expect_failure().await;
+        assert_eq!(response.status_code(), 409);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn login_with_wrong_password() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db).await?;
+
+        server
+            .post("/api/v1/auth/register")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+        let response = server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": "new@mail.com", "password": "battery staple" }))
+            .expect_failure()
+            .await;
+
+        assert_eq!(response.status_code(), 401);
+        assert_eq!(response.json::<serde_json::Value>()["error"], "invalid_credentials");
+
+        let response = server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": "unknown@mail.com", "password": "correct horse" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 401);
+        assert_eq!(response.json::<serde_json::Value>()["error"], "invalid_credentials");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_token_scopes() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let response = server
+            .post("/api/v1/auth/tokens")
+            .json(&json!({ "name": "ci", "scopes": ["notes:read"] }))
+            .await;
+        assert_eq!(response.status_code(), 201);
+        let created = response.json::<CreatedApiToken>();
+
+        let server = token_client(db, &created.secret).await?;
+        server.get("/api/v1/notes").await;
+        let response = server
+            .post("/api/v1/notes")
+            .json(&json!({ "text": "hello", "title": "world" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 403);
+
+        // tokens can't manage tokens
+        let response = server.get("/api/v1/auth/tokens").expect_failure().await;
+        assert_eq!(response.status_code(), 403);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_api_token() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let created = server
+            .post("/api/v1/auth/tokens")
+            .json(&json!({ "name": "ci", "scopes": ["notes:read", "notes:write"] }))
+            .await
+            .json::<CreatedApiToken>();
+        let client = token_client(db, &created.secret).await?;
+        client.get("/api/v1/notes").await;
+
+        let tokens = server
+            .get("/api/v1/auth/tokens")
+            .await
+            .json::<FindApiTokensResponse>()
+            .results;
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens[0].last_used_at.is_some());
+
+        server
+            .delete(&format!("/api/v1/auth/tokens/{}", created.token.id))
+            .await;
+        let response = client.get("/api/v1/notes").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_api_token() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let created = server
+            .post("/api/v1/auth/tokens")
+            .json(&json!({ "name": "ci", "scopes": ["notes:read"], "expires_at": "2100-01-01T00:00:00Z" }))
+            .await
+            .json::<CreatedApiToken>();
+        db.call(|conn| {
+            conn.execute("UPDATE api_tokens SET expires_at = '2000-01-01 00:00:00+00:00'", [])
+                .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let client = token_client(db, &created.secret).await?;
+        let response = client.get("/api/v1/notes").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn logout() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = cookie_client(db).await?;
+
+        let response = server.post("/api/v1/auth/logout").await;
+        assert_eq!(response.status_code(), 204);
+
+        let response = server.get("/api/v1/auth/me").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn login_replaces_session() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = cookie_client(db).await?;
+
+        server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+
+        let sessions = server
+            .get("/api/v1/auth/sessions")
+            .await
+            .json::<FindSessionsResponse>()
+            .results;
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions[0].current);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_session() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+        let other = crate::auth::create_session(&db, TEST_USER_ID, Some("curl/8.0".into()), 3600).await?;
+
+        let sessions = server
+            .get("/api/v1/auth/sessions")
+            .await
+            .json::<FindSessionsResponse>()
+            .results;
+        assert_eq!(sessions.len(), 2);
+        let other_session = sessions.iter().find(|s| !s.current).unwrap();
+        assert_eq!(other_session.user_agent.as_deref(), Some("curl/8.0"));
+
+        server
+            .delete(&format!("/api/v1/auth/sessions/{}", other_session.id))
+            .await;
+        assert!(crate::auth::find_user_by_token(&db, &other, 3600).await?.is_none());
+
+        let response = server
+            .delete(&format!("/api/v1/auth/sessions/{}", other_session.id))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sliding_expiry() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let now = chrono::Utc::now();
+        let soon = now + chrono::Duration::minutes(1);
+        db.call(move |conn| {
+            conn.execute(
+                "UPDATE sessions SET expires_at = ?, last_seen_at = ?",
+                params![soon, now - chrono::Duration::minutes(2)],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let sessions = server
+            .get("/api/v1/auth/sessions")
+            .await
+            .json::<FindSessionsResponse>()
+            .results;
+        assert!(sessions[0].expires_at > soon + chrono::Duration::days(1));
+
+        // seen within the last minute, not written again
+        db.call(move |conn| {
+            conn.execute("UPDATE sessions SET expires_at = ?", [soon]).unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let sessions = server
+            .get("/api/v1/auth/sessions")
+            .await
+            .json::<FindSessionsResponse>()
+            .results;
+        assert_eq!(sessions[0].expires_at, soon);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jwt_access_token() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+        server
+            .post("/api/v1/auth/register")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+
+        let pair = server
+            .post("/api/v1/auth/token")
+            .json(&json!({ "grant_type": "password", "email": "new@mail.com", "password": "correct horse" }))
+            .await
+            .json::<TokenPair>();
+
+        let client = token_client(db, &pair.access_token).await?;
+        let response = client.get("/api/v1/auth/me").await;
+        assert_eq!(response.json::<User>().email, "new@mail.com");
+
+        let jwks = server.get("/.well-known/jwks.json").await.json::<serde_json::Value>();
+        assert_eq!(jwks["keys"][0]["kid"], "test");
+        assert_eq!(jwks["keys"][0]["use"], "sig");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_token_reuse() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+        server
+            .post("/api/v1/auth/register")
+            .json(&json!({ "email": "new@mail.com", "password": "correct horse" }))
+            .await;
+
+        let first = server
+            .post("/api/v1/auth/token")
+            .json(&json!({ "grant_type": "password", "email": "new@mail.com", "password": "correct horse" }))
+            .await
+            .json::<TokenPair>();
+        let refresh = |refresh_token: String| {
+            server
+                .post("/api/v1/auth/token")
+                .json(&json!({ "grant_type": "refresh_token", "refresh_token": refresh_token }))
+        };
+
+        let second = refresh(first.refresh_token.clone()).await.json::<TokenPair>();
+        assert_ne!(second.refresh_token, first.refresh_token);
+
+        let response = refresh(first.refresh_token).expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+
+        // the reuse revoked the token that replaced it too
+        let response = refresh(second.refresh_token).expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    /// Server authenticated as a freshly registered user by the `session` cookie only.
+    async fn cookie_client(db: DB) -> Result<TestServer> {
+        let mut server = test_server(db).await?;
+        server.clear_headers();
+
+        let args = json!({ "email": "new@mail.com", "password": "correct horse" });
+        server.post("/api/v1/auth/register").json(&args).await;
+        server.post("/api/v1/auth/login").json(&args).await;
+        Ok(server)
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, |state| {
+            super::router(state.clone()).merge(crate::notes::router(state))
+        })
+        .await
+    }
+
+    async fn token_client(db: DB, secret: &str) -> Result<TestServer> {
+        let mut server = test_server(db).await?;
+        server.clear_headers();
+        server.add_header(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {secret}")).unwrap(),
+        );
+        Ok(server)
+    }
+}
@@ -0,0 +1,13 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Opaque random token handed out to clients. Only its [`hash`] is stored.
+pub fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
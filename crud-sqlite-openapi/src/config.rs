@@ -1,21 +1,78 @@
-use std::sync::OnceLock;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 
-use serde::Deserialize;
-
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum RegistrationMode {
+    /// Anyone can sign up.
+    #[default]
     Free,
+    /// Only emails listed in `registration_whitelist` can sign up.
     WhiteList,
+    /// Anyone can sign up, but stays `pending` until approved.
     WaitList,
 }
 
+/// OpenID Connect provider used for the authorization code flow.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OAuthProvider {
+    /// Identifies the provider in `/api/v1/auth/oauth/{name}/...` and `users.oauth_provider`.
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[serde(default = "default_oauth_scopes")]
+    pub scopes: String,
+}
+
+/// Ed25519 key signing JWT access tokens.
+#[derive(Deserialize, Debug, Clone)]
+pub struct JwtKey {
+    /// Published as `kid` in token headers and the JWKS.
+    pub kid: String,
+    /// Base64url encoded 32 byte seed, e.g. `openssl rand -base64 32 | tr '+/' '-_' | tr -d '='`.
+    pub private_key: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
     #[serde(default = "default_database_url")]
     pub database_url: String,
+    /// Where clients reach the API, used to build OAuth redirect URIs.
+    #[serde(default = "default_public_url")]
+    pub public_url: String,
+
+    // auth
+    #[serde(default)]
+    pub registration_mode: RegistrationMode,
+    /// Comma separated emails allowed to sign up in `white_list` mode.
+    #[serde(default)]
+    pub registration_whitelist: Vec<String>,
+    #[serde(default = "default_session_ttl_seconds")]
+    pub session_ttl_seconds: i64,
+    /// JSON array of [`OAuthProvider`]s.
+    #[serde(default, deserialize_with = "from_json")]
+    pub oauth_providers: Vec<OAuthProvider>,
+    /// JSON array of [`JwtKey`]s. The first one signs, all of them verify, so a new key is rotated in
+    /// by putting it first and the old one is dropped once its tokens expired.
+    #[serde(default, deserialize_with = "from_json")]
+    pub jwt_keys: Vec<JwtKey>,
+    #[serde(default = "default_jwt_access_ttl_seconds")]
+    pub jwt_access_ttl_seconds: i64,
+    #[serde(default = "default_jwt_refresh_ttl_seconds")]
+    pub jwt_refresh_ttl_seconds: i64,
+
+    // notes
+    /// Trashed notes are purged for good this many days after deletion.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: i64,
+    /// Largest accepted attachment, in bytes.
+    #[serde(default = "default_attachment_max_bytes")]
+    pub attachment_max_bytes: u64,
+    /// Largest accepted import file, in bytes. Also bounds the Markdown files of a zip once unpacked.
+    #[serde(default = "default_import_max_bytes")]
+    pub import_max_bytes: u64,
 
     // build
     pub app_version: Option<String>,
@@ -37,29 +94,54 @@ fn default_database_url() -> String {
     "sqlite.db".into()
 }
 
-fn default_local() -> String {
-    "local".into()
+fn default_public_url() -> String {
+    format!("http://127.0.0.1:{}", default_port())
 }
 
-impl Config {
-    pub fn from_env() -> Self {
-        dotenvy::dotenv().ok();
-        let config = envy::from_env::<Self>().unwrap();
+fn default_oauth_scopes() -> String {
+    "openid email".into()
+}
 
-        config
-    }
+fn default_session_ttl_seconds() -> i64 {
+    60 * 60 * 24 * 14
 }
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
+fn default_jwt_access_ttl_seconds() -> i64 {
+    60 * 15
+}
 
-pub fn config() -> &'static Config {
-    CONFIG.get_or_init(|| Config::from_env())
+fn default_jwt_refresh_ttl_seconds() -> i64 {
+    60 * 60 * 24 * 30
 }
 
-#[cfg(test)]
-pub fn config_override<F>(override_config: F) -> &'static Config
+fn default_trash_retention_days() -> i64 {
+    30
+}
+
+fn default_attachment_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_import_max_bytes() -> u64 {
+    32 * 1024 * 1024
+}
+
+fn default_local() -> String {
+    "local".into()
+}
+
+fn from_json<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
-    F: FnOnce(Config) -> Config,
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
 {
-    CONFIG.get_or_init(|| override_config(Config::from_env()))
+    let value = String::deserialize(deserializer)?;
+    serde_json::from_str(&value).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        envy::from_env::<Self>().unwrap()
+    }
 }
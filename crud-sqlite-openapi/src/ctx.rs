@@ -1,14 +1,17 @@
+use std::str::FromStr;
+
 use axum::{
     extract::{Extension, FromRequestParts, Request},
-    http::{request::Parts, HeaderMap},
+    http::{header, request::Parts, HeaderMap},
     middleware::Next,
     response::Response,
 };
+use axum_extra::extract::CookieJar;
 use schemars::JsonSchema;
-use serde::Serialize;
-use uuid::{uuid, Uuid};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::DB;
+use crate::{auth, policy::Permission, state::AppState, Error, DB};
 
 #[derive(Clone, Debug, FromRequestParts)]
 pub struct BaseParams {
@@ -23,41 +26,174 @@ impl BaseParams {
     }
 }
 
-#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Member,
+    Guest,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Member => "member",
+            Self::Guest => "guest",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Self::Admin),
+            "member" => Ok(Self::Member),
+            "guest" => Ok(Self::Guest),
+            _ => Err(Error::Unexpected(format!("Unknown role: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    Active,
+    Pending,
+    Blocked,
+}
+
+impl UserStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Pending => "pending",
+            Self::Blocked => "blocked",
+        }
+    }
+}
+
+impl FromStr for UserStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(Self::Active),
+            "pending" => Ok(Self::Pending),
+            "blocked" => Ok(Self::Blocked),
+            _ => Err(Error::Unexpected(format!("Unknown user status: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
+    pub role: Role,
+    pub status: UserStatus,
 }
 
 #[derive(Clone, Debug)]
 pub struct Ctx {
     pub user: Option<User>,
+    /// Scopes of the API token the caller authenticated with, `None` for sessions.
+    pub scopes: Option<Vec<Permission>>,
+    /// Session the caller authenticated with, `None` for API tokens and JWTs.
+    pub session_id: Option<Uuid>,
 }
 
 impl Ctx {
     pub fn new(user: Option<User>) -> Self {
-        Self { user }
+        Self {
+            user,
+            scopes: None,
+            session_id: None,
+        }
     }
 
     pub fn get_user_id(&self) -> Option<Uuid> {
         self.user.as_ref().map(|u| u.id)
     }
+
+    /// Resolves the caller from `Authorization: Bearer <token>` or the session cookie.
+    /// The bearer token is either a session token, an API token or a JWT access token.
+    /// Missing, unknown or expired credentials give an anonymous `Ctx`.
+    pub async fn resolve(AppState { conn: db, config, keys }: &AppState, headers: &HeaderMap) -> crate::Result<Self> {
+        let Some(token) = bearer(headers).or_else(|| session_cookie(headers)) else {
+            return Ok(Self::new(None));
+        };
+
+        if token.starts_with(auth::API_TOKEN_PREFIX) {
+            let ctx = match auth::find_user_by_api_token(db, &token).await? {
+                Some((user, scopes)) => Self {
+                    user: Some(user),
+                    scopes: Some(scopes),
+                    session_id: None,
+                },
+                None => Self::new(None),
+            };
+            return Ok(ctx);
+        }
+
+        if auth::jwt::is_jwt(&token) {
+            let user = auth::jwt::find_user_by_jwt(db, keys, &config.public_url, &token).await?;
+            return Ok(Self::new(user));
+        }
+
+        let ctx = match auth::find_user_by_token(db, &token, config.session_ttl_seconds).await? {
+            Some((user, session_id)) => Self {
+                user: Some(user),
+                scopes: None,
+                session_id: Some(session_id),
+            },
+            None => Self::new(None),
+        };
+        Ok(ctx)
+    }
 }
 
+fn bearer(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    CookieJar::from_headers(headers)
+        .get(auth::SESSION_COOKIE)
+        .map(|c| c.value().to_string())
+}
+
+/// Requires an authenticated, active caller. The `Ctx` resolved by [`with_ctx`] is reused when present.
 impl<S> FromRequestParts<S> for Ctx
 where
     S: Send + Sync,
 {
-    type Rejection = Response;
-
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        Ok(Self {
-            // TODO
-            user: Some(User {
-                id: uuid!("018f6146-32f4-7948-8289-cfb5cdb2b2af"),
-                email: "fake@mail.com".into(),
-            }),
-        })
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = match parts.extensions.get::<Ctx>() {
+            Some(ctx) => ctx.clone(),
+            None => {
+                let Extension(app) = Extension::<AppState>::from_request_parts(parts, state)
+                    .await
+                    .map_err(|e| Error::Unexpected(e.body_text()))?;
+                let ctx = Ctx::resolve(&app, &parts.headers).await?;
+                parts.extensions.insert(ctx.clone());
+                ctx
+            }
+        };
+
+        match &ctx.user {
+            None => Err(Error::Unauthorized),
+            Some(user) if user.status != UserStatus::Active => Err(Error::Forbidden),
+            Some(_) => Ok(ctx),
+        }
     }
 }
 
@@ -71,8 +207,16 @@ tokio::task_local! {
     pub static REQ_CTX: ReqCtx;
 }
 
-pub async fn with_ctx(headers: HeaderMap, ctx: Ctx, request: Request, next: Next) -> crate::Result<Response> {
-    Ok(REQ_CTX
+pub async fn with_ctx(
+    Extension(app): Extension<AppState>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> crate::Result<Response> {
+    let ctx = Ctx::resolve(&app, &headers).await?;
+    request.extensions_mut().insert(ctx.clone());
+
+    let response = REQ_CTX
         .scope(
             ReqCtx {
                 headers,
@@ -80,5 +224,90 @@ pub async fn with_ctx(headers: HeaderMap, ctx: Ctx, request: Request, next: Next
             },
             next.run(request),
         )
-        .await)
+        .await;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderValue};
+    use uuid::uuid;
+
+    use crate::{
+        auth,
+        db::init_test_db,
+        errors::Result,
+        tests::{authenticate, test_server, TEST_USER_ID},
+    };
+
+    #[tokio::test]
+    async fn rejects_anonymous() -> Result<()> {
+        let db = init_test_db().await?;
+        let mut server = test_server(db, crate::notes::router).await?;
+        server.clear_headers();
+
+        let response = server.get("/api/v1/notes").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_token() -> Result<()> {
+        let db = init_test_db().await?;
+        let mut server = test_server(db, crate::notes::router).await?;
+        server.clear_headers();
+
+        let response = server
+            .get("/api/v1/notes")
+            .add_header(header::AUTHORIZATION, HeaderValue::from_static("Bearer nope"))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_inactive_users() -> Result<()> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000001'), 'pending@mail.com', 'pending');
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'blocked@mail.com', 'blocked');
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let mut server = test_server(db.clone(), crate::notes::router).await?;
+        for user_id in [
+            uuid!("018f6146-32f4-7948-8289-000000000001"),
+            uuid!("018f6146-32f4-7948-8289-000000000002"),
+        ] {
+            authenticate(&db, &mut server, user_id).await?;
+            let response = server.get("/api/v1/notes").expect_failure().await;
+            assert_eq!(response.status_code(), 403);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accepts_session_cookie() -> Result<()> {
+        let db = init_test_db().await?;
+        let token = auth::create_session(&db, TEST_USER_ID, None, 3600).await?;
+
+        let mut server = test_server(db, crate::notes::router).await?;
+        server.clear_headers();
+
+        let response = server
+            .get("/api/v1/notes")
+            .add_cookie(axum_extra::extract::cookie::Cookie::new(auth::SESSION_COOKIE, token))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        Ok(())
+    }
 }
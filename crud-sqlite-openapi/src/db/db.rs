@@ -2,8 +2,6 @@ use rusqlite::functions::FunctionFlags;
 use tokio_rusqlite::Connection;
 use uuid::Uuid;
 
-use crate::config;
-
 use super::migrations::MIGRATIONS;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -44,8 +42,8 @@ impl From<rusqlite::Error> for Error {
 
 pub type DB = Connection;
 
-pub async fn init_db() -> Result<DB> {
-    let conn = tokio_rusqlite::Connection::open(&config().database_url).await?;
+pub async fn init_db(database_url: &str) -> Result<DB> {
+    let conn = tokio_rusqlite::Connection::open(database_url).await?;
 
     conn.call(|conn| {
         add_uuid_functions(conn)?;
@@ -70,6 +68,8 @@ pub async fn init_test_db() -> Result<DB> {
 
         MIGRATIONS.to_latest(conn).unwrap();
 
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+
         Ok(())
     })
     .await?;
@@ -1,5 +1,7 @@
 use lazy_static::lazy_static;
-use rusqlite_migration::{Migrations, M};
+use rusqlite::{params, Transaction};
+use rusqlite_migration::{HookResult, Migrations, M};
+use uuid::Uuid;
 
 lazy_static! {
     static ref DEV_FIXTURES: String = _dev_fixtures();
@@ -45,9 +47,394 @@ lazy_static! {
         "#
         ),
         M::up(&DEV_FIXTURES),
+        M::up(
+            r#"
+            CREATE TABLE sessions (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                token_hash TEXT NOT NULL UNIQUE, -- sha256 of the session token
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE api_tokens (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                name TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE, -- sha256 of the token
+                scopes TEXT NOT NULL, -- space separated, e.g. 'notes:read notes:write'
+
+                expires_at DATETIME,
+                last_used_at DATETIME,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE oauth_states (
+                state TEXT PRIMARY KEY NOT NULL,
+                provider TEXT NOT NULL,
+                code_verifier TEXT NOT NULL, -- PKCE
+                nonce TEXT NOT NULL,
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL
+            );
+        "#
+        ),
+        M::up(
+            r#"
+            ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+            ALTER TABLE sessions ADD COLUMN last_seen_at DATETIME;
+            CREATE INDEX sessions_user_id ON sessions (user_id);
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE refresh_tokens (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                family_id BLOB NOT NULL CHECK(length(family_id) = 16), -- tokens refreshed from the same login
+                token_hash TEXT NOT NULL UNIQUE, -- sha256 of the token
+
+                used_at DATETIME, -- one-time use, a second use revokes the family
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+            CREATE INDEX refresh_tokens_family_id ON refresh_tokens (family_id);
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE note_shares (
+                note_id BLOB NOT NULL CHECK(length(note_id) = 16),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                permission TEXT NOT NULL, -- read | write
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                created_by BLOB CHECK(length(created_by) = 16),
+
+                PRIMARY KEY (note_id, user_id),
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+                FOREIGN KEY (created_by) REFERENCES users (id)
+            );
+            CREATE INDEX note_shares_user_id ON note_shares (user_id);
+        "#
+        ),
+        M::up(
+            r#"
+            -- external content table over notes, joined on the implicit rowid;
+            -- INSERT INTO notes_fts(notes_fts) VALUES ('rebuild') restores it should rowids change
+            CREATE VIRTUAL TABLE notes_fts USING fts5(
+                title,
+                text,
+                content = 'notes',
+                content_rowid = 'rowid',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+
+            CREATE TRIGGER notes_fts_insert AFTER INSERT ON notes BEGIN
+                INSERT INTO notes_fts (rowid, title, text) VALUES (new.rowid, new.title, new.text);
+            END;
+            CREATE TRIGGER notes_fts_delete AFTER DELETE ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, title, text) VALUES ('delete', old.rowid, old.title, old.text);
+            END;
+            CREATE TRIGGER notes_fts_update AFTER UPDATE OF title, text ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, title, text) VALUES ('delete', old.rowid, old.title, old.text);
+                INSERT INTO notes_fts (rowid, title, text) VALUES (new.rowid, new.title, new.text);
+            END;
+
+            INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE note_revisions (
+                note_id BLOB NOT NULL CHECK(length(note_id) = 16),
+                revision INTEGER NOT NULL, -- 1 for the note as created
+                title TEXT,
+                text TEXT,
+
+                updated_at DATETIME NOT NULL,
+                updated_by BLOB CHECK(length(updated_by) = 16),
+
+                PRIMARY KEY (note_id, revision),
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE,
+                FOREIGN KEY (updated_by) REFERENCES users (id)
+            );
+
+            CREATE TRIGGER note_revisions_insert AFTER INSERT ON notes BEGIN
+                INSERT INTO note_revisions (note_id, revision, title, text, updated_at, updated_by)
+                VALUES (new.id, 1, new.title, new.text, new.created_at, new.created_by);
+            END;
+            CREATE TRIGGER note_revisions_update AFTER UPDATE OF title, text ON notes
+            WHEN old.title IS NOT new.title OR old.text IS NOT new.text BEGIN
+                INSERT INTO note_revisions (note_id, revision, title, text, updated_at, updated_by)
+                VALUES (
+                    new.id,
+                    (SELECT coalesce(max(revision), 0) + 1 FROM note_revisions WHERE note_id = new.id),
+                    new.title,
+                    new.text,
+                    coalesce(new.updated_at, CURRENT_TIMESTAMP),
+                    new.updated_by
+                );
+            END;
+
+            INSERT INTO note_revisions (note_id, revision, title, text, updated_at, updated_by)
+            SELECT id, 1, title, text, coalesce(updated_at, created_at), coalesce(updated_by, created_by) FROM notes;
+        "#
+        ),
+        M::up(
+            r#"
+            ALTER TABLE notes ADD COLUMN deleted_at DATETIME; -- in the trash since
+            ALTER TABLE notes ADD COLUMN deleted_by BLOB CHECK(length(deleted_by) = 16) REFERENCES users (id);
+            CREATE INDEX notes_deleted_at ON notes (deleted_at) WHERE deleted_at IS NOT NULL;
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE tags (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16), -- owner of the tagged notes
+                name TEXT NOT NULL COLLATE NOCASE,
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE note_tags (
+                note_id BLOB NOT NULL CHECK(length(note_id) = 16),
+                tag_id BLOB NOT NULL CHECK(length(tag_id) = 16),
+
+                PRIMARY KEY (note_id, tag_id),
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+            );
+            CREATE INDEX note_tags_tag_id ON note_tags (tag_id);
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE notebooks (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                parent_id BLOB CHECK(length(parent_id) = 16), -- NULL at the top level
+                name TEXT NOT NULL,
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+                FOREIGN KEY (parent_id) REFERENCES notebooks (id) ON DELETE CASCADE
+            );
+            CREATE INDEX notebooks_user_id ON notebooks (user_id);
+            CREATE INDEX notebooks_parent_id ON notebooks (parent_id);
+
+            ALTER TABLE notes ADD COLUMN notebook_id BLOB CHECK(length(notebook_id) = 16) REFERENCES notebooks (id) ON DELETE SET NULL;
+            CREATE INDEX notes_notebook_id ON notes (notebook_id);
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE attachments (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                note_id BLOB NOT NULL CHECK(length(note_id) = 16),
+                file_name TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                checksum TEXT, -- sha256 of the content, NULL until the upload completes
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                created_by BLOB CHECK(length(created_by) = 16),
+
+                -- last, so that reading the other columns skips its overflow pages;
+                -- allocated with zeroblob(size) and written through incremental blob I/O
+                data BLOB NOT NULL,
+
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE,
+                FOREIGN KEY (created_by) REFERENCES users (id)
+            );
+            CREATE INDEX attachments_note_id ON attachments (note_id);
+        "#
+        ),
+        M::up(
+            r#"
+            ALTER TABLE notes ADD COLUMN version INTEGER NOT NULL DEFAULT 1; -- sent as the ETag
+
+            CREATE TRIGGER notes_version AFTER UPDATE OF title, text, notebook_id ON notes BEGIN
+                UPDATE notes SET version = old.version + 1 WHERE id = new.id;
+            END;
+        "#
+        ),
+        M::up(
+            r#"
+            CREATE TABLE note_html (
+                note_id BLOB PRIMARY KEY CHECK(length(note_id) = 16) NOT NULL,
+                version INTEGER NOT NULL, -- notes.version the text was rendered at
+                html TEXT NOT NULL,
+
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE
+            );
+        "#
+        ),
+        M::up_with_hook(
+            r#"
+            CREATE TABLE note_links (
+                source_id BLOB NOT NULL CHECK(length(source_id) = 16),
+                reference TEXT NOT NULL COLLATE NOCASE, -- between [[ and ]], a note title or id
+                reference_id BLOB CHECK(length(reference_id) = 16), -- set when the reference is an id
+                target_id BLOB CHECK(length(target_id) = 16), -- NULL while dangling
+
+                PRIMARY KEY (source_id, reference),
+                FOREIGN KEY (source_id) REFERENCES notes (id) ON DELETE CASCADE,
+                FOREIGN KEY (target_id) REFERENCES notes (id) ON DELETE SET NULL
+            );
+            CREATE INDEX note_links_target_id ON note_links (target_id);
+            CREATE INDEX note_links_dangling ON note_links (reference) WHERE target_id IS NULL;
+        "#,
+            index_note_links
+        ),
+        M::up(
+            r#"
+            ALTER TABLE notes ADD COLUMN due_at DATETIME;
+            ALTER TABLE notes ADD COLUMN remind_at DATETIME;
+            ALTER TABLE notes ADD COLUMN reminded_at DATETIME; -- when the reminder fired, NULL while pending
+            CREATE INDEX notes_pending_reminders ON notes (remind_at) WHERE remind_at IS NOT NULL AND reminded_at IS NULL;
+
+            -- a new reminder time fires again
+            CREATE TRIGGER notes_remind_at AFTER UPDATE OF remind_at ON notes WHEN new.remind_at IS NOT old.remind_at BEGIN
+                UPDATE notes SET reminded_at = NULL WHERE id = new.id;
+            END;
+
+            DROP TRIGGER notes_version;
+            CREATE TRIGGER notes_version AFTER UPDATE OF title, text, notebook_id, due_at, remind_at ON notes BEGIN
+                UPDATE notes SET version = old.version + 1 WHERE id = new.id;
+            END;
+
+            CREATE TABLE notifications (
+                id BLOB PRIMARY KEY CHECK(length(id) = 16) NOT NULL UNIQUE DEFAULT (uuid7_now()),
+                user_id BLOB NOT NULL CHECK(length(user_id) = 16),
+                note_id BLOB NOT NULL CHECK(length(note_id) = 16),
+                title TEXT NOT NULL, -- of the note when the reminder fired
+                due_at DATETIME,
+                remind_at DATETIME NOT NULL,
+
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                read_at DATETIME,
+
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+                FOREIGN KEY (note_id) REFERENCES notes (id) ON DELETE CASCADE
+            );
+            CREATE INDEX notifications_user_id ON notifications (user_id, id);
+            CREATE INDEX notifications_note_id ON notifications (note_id);
+        "#
+        ),
+        M::up(
+            r#"
+            -- the implicit rowid of notes may change on VACUUM since its primary key is a BLOB,
+            -- notes_fts is keyed on the INTEGER PRIMARY KEY of note_search_ids instead
+            DROP TRIGGER notes_fts_insert;
+            DROP TRIGGER notes_fts_delete;
+            DROP TRIGGER notes_fts_update;
+            DROP TABLE notes_fts;
+
+            CREATE TABLE note_search_ids (
+                search_id INTEGER PRIMARY KEY,
+                note_id BLOB NOT NULL UNIQUE CHECK(length(note_id) = 16)
+            );
+            INSERT INTO note_search_ids (note_id) SELECT id FROM notes ORDER BY rowid;
+
+            CREATE VIEW notes_search AS
+            SELECT note_search_ids.search_id, notes.title, notes.text FROM note_search_ids
+            JOIN notes ON notes.id = note_search_ids.note_id;
+
+            CREATE VIRTUAL TABLE notes_fts USING fts5(
+                title,
+                text,
+                content = 'notes_search',
+                content_rowid = 'search_id',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+
+            CREATE TRIGGER notes_fts_insert AFTER INSERT ON notes BEGIN
+                INSERT INTO note_search_ids (note_id) VALUES (new.id);
+                INSERT INTO notes_fts (rowid, title, text)
+                VALUES ((SELECT search_id FROM note_search_ids WHERE note_id = new.id), new.title, new.text);
+            END;
+            CREATE TRIGGER notes_fts_delete AFTER DELETE ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, title, text)
+                VALUES ('delete', (SELECT search_id FROM note_search_ids WHERE note_id = old.id), old.title, old.text);
+                DELETE FROM note_search_ids WHERE note_id = old.id;
+            END;
+            CREATE TRIGGER notes_fts_update AFTER UPDATE OF title, text ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, title, text)
+                VALUES ('delete', (SELECT search_id FROM note_search_ids WHERE note_id = old.id), old.title, old.text);
+                INSERT INTO notes_fts (rowid, title, text)
+                VALUES ((SELECT search_id FROM note_search_ids WHERE note_id = new.id), new.title, new.text);
+            END;
+
+            INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');
+        "#
+        ),
     ]);
 }
 
+/// Stores the `[[...]]` links of the notes written before links were tracked. A copy of the indexing
+/// in `links` as it was then, so that changes to it don't change what this migration does.
+fn index_note_links(tx: &Transaction) -> HookResult {
+    let notes = tx
+        .prepare("SELECT id, text FROM notes")?
+        .query_map([], |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut insert =
+        tx.prepare("INSERT OR IGNORE INTO note_links (source_id, reference, reference_id) VALUES (?, ?, ?)")?;
+    for (note_id, text) in notes {
+        let references = text
+            .split("[[")
+            .skip(1)
+            .filter_map(|rest| rest.split_once("]]"))
+            .map(|(reference, _)| reference.trim())
+            .filter(|reference| !reference.is_empty() && !reference.contains(['\n', '[', ']']));
+        for reference in references {
+            insert.execute(params![note_id, reference, Uuid::parse_str(reference).ok()])?;
+        }
+    }
+
+    tx.execute(
+        r#"UPDATE note_links SET target_id = coalesce(
+            (SELECT notes.id FROM notes
+            JOIN notes AS source ON source.id = note_links.source_id
+            WHERE notes.id = note_links.reference_id AND (
+                notes.created_by IS source.created_by
+                OR EXISTS (SELECT 1 FROM note_shares WHERE note_id = notes.id AND user_id = source.created_by)
+            )),
+            (SELECT notes.id FROM notes
+            JOIN notes AS source ON source.id = note_links.source_id
+            WHERE note_links.reference_id IS NULL
+                AND notes.title = note_links.reference COLLATE NOCASE
+                AND notes.created_by IS source.created_by
+            ORDER BY notes.deleted_at IS NOT NULL, notes.id
+            LIMIT 1)
+        )"#,
+        [],
+    )?;
+    Ok(())
+}
+
 fn _dev_fixtures() -> String {
     let user_id = "018f6146-32f4-7948-8289-cfb5cdb2b2af";
     format!(
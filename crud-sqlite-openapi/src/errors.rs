@@ -3,6 +3,7 @@ use std::sync::{Arc, OnceLock};
 use crate::error_responses;
 use axum::{
     extract::{
+        multipart::{MultipartError, MultipartRejection},
         rejection::{JsonRejection, PathRejection, QueryRejection},
         Request,
     },
@@ -14,7 +15,7 @@ use schemars::{
     schema::{Schema, SchemaObject, SubschemaValidation},
     schema_for, schema_for_value, JsonSchema,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 pub use response::{ErrorResponse, ErrorResponseDocs};
@@ -26,19 +27,39 @@ pub enum Error {
     #[error("not_found")]
     NotFound(String),
 
+    #[error("conflict")]
+    Conflict(String),
+
+    #[error("precondition_failed")]
+    PreconditionFailed(String),
+
     // auth
     #[error("unauthorized")]
     Unauthorized,
+    #[error("invalid_credentials")]
+    InvalidCredentials,
     #[error("forbidden")]
     Forbidden,
+    #[error("registration_closed")]
+    RegistrationClosed,
+    #[error("oauth")]
+    OAuth(String),
+    #[error("oauth_provider")]
+    OAuthProvider(String),
 
     // validation
     #[error("validation")]
+    Validation(String),
+    #[error("validation")]
     JsonValidation(JsonRejection),
     #[error("validation")]
     QueryValidation(#[from] QueryRejection),
     #[error("validation")]
     PathValidation(#[from] PathRejection),
+    #[error("validation")]
+    MultipartValidation(#[from] MultipartRejection),
+    #[error("payload_too_large")]
+    PayloadTooLarge(String),
 
     #[error(transparent)]
     DB(crate::db::Error),
@@ -58,10 +79,22 @@ impl From<JsonRejection> for Error {
     }
 }
 
+/// Reading a multipart field fails on malformed bodies and the body size limit.
+impl From<MultipartError> for Error {
+    fn from(error: MultipartError) -> Self {
+        if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            Self::PayloadTooLarge(error.body_text())
+        } else {
+            Self::Validation(error.body_text())
+        }
+    }
+}
+
 impl From<crate::db::Error> for Error {
     fn from(error: crate::db::Error) -> Self {
         match error {
             crate::db::Error::NotFound(msg) => Self::NotFound(msg),
+            crate::db::Error::TokioRusqlite(error @ crate::db::tokio_rusqlite::Error::Other(_)) => Self::from(error),
             error => Self::DB(error),
         }
     }
@@ -84,7 +117,7 @@ pub mod db_mappers {
                     if err.is::<Error>() {
                         return *err.downcast::<Error>().unwrap();
                     }
-                    return Error::DB(tokio_rusqlite::Error::Other(err).into());
+                    Error::DB(tokio_rusqlite::Error::Other(err).into())
                 }
                 _ => Error::DB(error.into()),
             }
@@ -108,11 +141,20 @@ pub mod db_mappers {
 
 error_responses! {
     not_found: 404,
+    conflict: 409,
+    precondition_failed: 412,
+    validation: 400,
     path_validation: 400,
     query_validation: 400,
     json_validation: 400,
+    multipart_validation: 400,
+    payload_too_large: 413,
     unauthorized: 401,
+    invalid_credentials: 401,
     forbidden: 403,
+    registration_closed: 403,
+    oauth: 401,
+    oauth_provider: 502,
     unexpected: 500
 }
 
@@ -121,11 +163,22 @@ impl From<&Error> for ErrorResponse {
         let errors = errors();
         match error {
             Error::NotFound(message) => errors.not_found.with_message(message),
+            Error::Conflict(message) => errors.conflict.with_message(message),
+            Error::PreconditionFailed(message) => errors.precondition_failed.with_message(message),
             Error::Unauthorized => errors.unauthorized.with_message("Unauthorized"),
+            Error::InvalidCredentials => errors.invalid_credentials.with_message("Invalid email or password"),
             Error::Forbidden => errors.forbidden.with_message("Forbitten"),
+            Error::RegistrationClosed => errors
+                .registration_closed
+                .with_message("Registration is closed for this email"),
+            Error::OAuth(message) => errors.oauth.with_message(message),
+            Error::OAuthProvider(message) => errors.oauth_provider.with_message(message),
+            Error::Validation(message) => errors.validation.with_message(message),
             Error::JsonValidation(json_error) => errors.json_validation.with_message(json_error.body_text()),
             Error::QueryValidation(error) => errors.query_validation.with_message(error.body_text()),
             Error::PathValidation(error) => errors.path_validation.with_message(error.body_text()),
+            Error::MultipartValidation(error) => errors.multipart_validation.with_message(error.body_text()),
+            Error::PayloadTooLarge(message) => errors.payload_too_large.with_message(message),
             Error::App(app_error) => {
                 let msg = app_error.to_string();
                 errors.unexpected.with_message(msg)
@@ -166,7 +219,7 @@ mod response {
 
     use super::*;
 
-    #[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
     pub struct ErrorResponse {
         pub error: String,
         pub message: Option<String>,
@@ -0,0 +1,277 @@
+use std::convert::Infallible;
+
+use aide::{
+    generate::GenContext,
+    openapi::{
+        Header, HeaderStyle, Operation, Parameter, ParameterData, ParameterSchemaOrContent, ReferenceOr, Response,
+        SchemaObject,
+    },
+    operation::{add_parameters, OperationInput},
+    OperationOutput,
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderName, StatusCode},
+    response::IntoResponse,
+};
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Entity tag of a version of a resource, `"<version>"`, or `"<version>-<representation>"` for another
+/// representation of the same version, e.g. the note with its text rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub version: u32,
+    pub representation: Option<String>,
+}
+
+impl EntityTag {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            representation: None,
+        }
+    }
+
+    /// Parses the opaque tag between the quotes.
+    fn parse(tag: &str) -> Option<Self> {
+        let (version, representation) = match tag.split_once('-') {
+            Some((version, representation)) if !representation.is_empty() => (version, Some(representation.into())),
+            Some(_) => return None,
+            None => (tag, None),
+        };
+        Some(Self {
+            version: version.parse().ok()?,
+            representation,
+        })
+    }
+}
+
+impl std::fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.representation {
+            Some(representation) => write!(f, "\"{}-{representation}\"", self.version),
+            None => write!(f, "\"{}\"", self.version),
+        }
+    }
+}
+
+/// Entity tags of a precondition header, compared against the version of a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// `*`, any current version.
+    Any,
+    /// The listed tags, tags of other formats never match.
+    Tags(Vec<EntityTag>),
+}
+
+impl Precondition {
+    /// Any representation of `version` is listed, for changes based on that version.
+    pub fn matches(&self, version: u32) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|tag| tag.version == version),
+        }
+    }
+
+    /// Exactly `tag` is listed, for cached representations.
+    pub fn matches_tag(&self, tag: &EntityTag) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.contains(tag),
+        }
+    }
+
+    /// Parses a list of entity tags, weak ones are only accepted with `weak`.
+    fn parse(headers: &HeaderMap, name: &HeaderName, weak: bool) -> Option<Self> {
+        let mut tags = Vec::new();
+        for value in headers.get_all(name) {
+            for tag in value.to_str().unwrap_or_default().split(',').map(str::trim) {
+                if tag == "*" {
+                    return Some(Self::Any);
+                }
+                let tag = match tag.strip_prefix("W/") {
+                    Some(tag) if weak => tag,
+                    Some(_) => continue,
+                    None => tag,
+                };
+                if let Some(tag) = tag.strip_prefix('"').and_then(|tag| tag.strip_suffix('"')) {
+                    tags.extend(EntityTag::parse(tag));
+                }
+            }
+        }
+        headers.contains_key(name).then_some(Self::Tags(tags))
+    }
+}
+
+/// `If-Match`, the request only applies to the listed versions.
+pub struct IfMatch(pub Option<Precondition>);
+
+/// `If-None-Match`, the client already has the listed versions.
+pub struct IfNoneMatch(pub Option<Precondition>);
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(Precondition::parse(&parts.headers, &header::IF_MATCH, false)))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(Precondition::parse(&parts.headers, &header::IF_NONE_MATCH, true)))
+    }
+}
+
+impl OperationInput for IfMatch {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        let parameter = header_parameter(
+            ctx,
+            "If-Match",
+            "`ETag` of the version the change is based on, fails with 412 once the resource changed.",
+        );
+        add_parameters(ctx, operation, [parameter]);
+    }
+}
+
+impl OperationInput for IfNoneMatch {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        let parameter = header_parameter(
+            ctx,
+            "If-None-Match",
+            "`ETag` of a cached version, responds with 304 while it's current.",
+        );
+        add_parameters(ctx, operation, [parameter]);
+    }
+}
+
+fn header_parameter(ctx: &mut GenContext, name: &str, description: &str) -> Parameter {
+    Parameter::Header {
+        parameter_data: ParameterData {
+            name: name.into(),
+            description: Some(description.into()),
+            required: false,
+            deprecated: None,
+            format: ParameterSchemaOrContent::Schema(SchemaObject {
+                json_schema: ctx.schema.subschema_for::<String>(),
+                example: None,
+                external_docs: None,
+            }),
+            example: None,
+            examples: IndexMap::new(),
+            explode: None,
+            extensions: IndexMap::new(),
+        },
+        style: HeaderStyle::Simple,
+    }
+}
+
+/// JSON body sent with the `ETag` of its version, or `304 Not Modified` without a body.
+pub struct Versioned<T> {
+    tag: EntityTag,
+    body: Option<T>,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(version: u32, body: T) -> Self {
+        Self {
+            tag: EntityTag::new(version),
+            body: Some(body),
+        }
+    }
+
+    /// Tags the body as another representation of the version, so that it's cached apart.
+    pub fn representation(mut self, representation: &str) -> Self {
+        self.tag.representation = Some(representation.into());
+        self
+    }
+
+    /// Drops the body when the client already has this representation of the version.
+    pub fn unless_cached(mut self, IfNoneMatch(cached): &IfNoneMatch) -> Self {
+        if cached.as_ref().is_some_and(|cached| cached.matches_tag(&self.tag)) {
+            self.body = None;
+        }
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for Versioned<T> {
+    fn into_response(self) -> axum::response::Response {
+        let etag = [(header::ETAG, self.tag.to_string())];
+        match self.body {
+            Some(body) => (etag, axum::Json(body)).into_response(),
+            None => (StatusCode::NOT_MODIFIED, etag).into_response(),
+        }
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Versioned<T> {
+    type Inner = T;
+
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<Response> {
+        let mut response = axum::Json::<T>::operation_response(ctx, operation)?;
+        response.headers.insert(
+            "ETag".into(),
+            ReferenceOr::Item(Header {
+                description: Some("Version of the resource, for `If-Match` and `If-None-Match`.".into()),
+                style: HeaderStyle::Simple,
+                required: true,
+                deprecated: None,
+                format: ParameterSchemaOrContent::Schema(SchemaObject {
+                    json_schema: ctx.schema.subschema_for::<String>(),
+                    example: None,
+                    external_docs: None,
+                }),
+                example: None,
+                examples: IndexMap::new(),
+                extensions: IndexMap::new(),
+            }),
+        );
+        Some(response)
+    }
+
+    fn inferred_responses(ctx: &mut GenContext, operation: &mut Operation) -> Vec<(Option<u16>, Response)> {
+        Self::operation_response(ctx, operation)
+            .map(|response| Vec::from([(Some(200), response)]))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn parse_preconditions() {
+        let parse = |value: &'static str, weak| {
+            let headers = HeaderMap::from_iter([(header::IF_MATCH, HeaderValue::from_static(value))]);
+            Precondition::parse(&headers, &header::IF_MATCH, weak)
+        };
+
+        let tags = |versions: &[u32]| {
+            let tags = versions.iter().copied().map(EntityTag::new).collect();
+            Some(Precondition::Tags(tags))
+        };
+        assert_eq!(parse("\"3\"", false), tags(&[3]));
+        assert_eq!(parse("\"1\", W/\"2\", \"x\", \"3-\"", false), tags(&[1]));
+        assert_eq!(parse("\"1\", W/\"2\"", true), tags(&[1, 2]));
+        assert_eq!(parse("*", false), Some(Precondition::Any));
+
+        let html = EntityTag {
+            version: 3,
+            representation: Some("html".into()),
+        };
+        let precondition = parse("\"3-html\"", false).unwrap();
+        assert_eq!(precondition, Precondition::Tags(vec![html.clone()]));
+        assert_eq!(html.to_string(), "\"3-html\"");
+        assert!(precondition.matches(3) && precondition.matches_tag(&html));
+        assert!(!precondition.matches_tag(&EntityTag::new(3)));
+        assert_eq!(Precondition::parse(&HeaderMap::new(), &header::IF_MATCH, false), None);
+    }
+}
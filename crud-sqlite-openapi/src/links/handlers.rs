@@ -0,0 +1,179 @@
+use rusqlite::{params, Connection, Params};
+use uuid::Uuid;
+
+use crate::{
+    ctx::BaseParams,
+    db,
+    notes::{require_access, Access},
+    Error, Result,
+};
+
+use super::{Backlink, DanglingLink, FindBacklinksResponse, GraphEdge, GraphNode, NoteGraph};
+
+/// Resolves the links matching `condition`: references that are an id point to that note if the owner of
+/// the linking note can read it, titles to the oldest note with that title (case insensitive) they own.
+const RESOLVE_LINKS: &str = r#"UPDATE note_links SET target_id = coalesce(
+        (SELECT notes.id FROM notes
+        JOIN notes AS source ON source.id = note_links.source_id
+        WHERE notes.id = note_links.reference_id AND (
+            notes.created_by IS source.created_by
+            OR EXISTS (SELECT 1 FROM note_shares WHERE note_id = notes.id AND user_id = source.created_by)
+        )),
+        (SELECT notes.id FROM notes
+        JOIN notes AS source ON source.id = note_links.source_id
+        WHERE note_links.reference_id IS NULL
+            AND notes.title = note_links.reference COLLATE NOCASE
+            AND notes.created_by IS source.created_by
+        ORDER BY notes.deleted_at IS NOT NULL, notes.id
+        LIMIT 1)
+    )"#;
+
+fn resolve_links(conn: &Connection, condition: &str, params: impl Params) -> rusqlite::Result<usize> {
+    conn.execute(&format!("{RESOLVE_LINKS} WHERE {condition}"), params)
+}
+
+/// The `[[...]]` references in `text`, trimmed and without empty ones.
+fn parse_links(text: &str) -> Vec<&str> {
+    text.split("[[")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("]]"))
+        .map(|(reference, _)| reference.trim())
+        .filter(|reference| !reference.is_empty() && !reference.contains(['\n', '[', ']']))
+        .collect()
+}
+
+/// Stores the links in the text of a note, then updates the links to it: links by its former
+/// title become dangling, dangling links by its title or id resolve to it.
+pub(crate) fn update_links(conn: &Connection, note_id: Uuid) -> rusqlite::Result<()> {
+    let (title, text) = conn.query_row("SELECT title, text FROM notes WHERE id = ?", params![note_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    conn.execute("DELETE FROM note_links WHERE source_id = ?", params![note_id])?;
+    let mut insert =
+        conn.prepare("INSERT OR IGNORE INTO note_links (source_id, reference, reference_id) VALUES (?, ?, ?)")?;
+    for reference in parse_links(&text) {
+        insert.execute(params![note_id, reference, Uuid::parse_str(reference).ok()])?;
+    }
+    resolve_links(conn, "source_id = ?", params![note_id])?;
+
+    let stale = conn
+        .prepare(
+            r#"UPDATE note_links SET target_id = NULL
+            WHERE target_id = ?1 AND reference_id IS NULL AND reference <> ?2
+            RETURNING reference"#,
+        )?
+        .query_map(params![note_id, title], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for reference in stale.iter().chain([&title]) {
+        resolve_links(
+            conn,
+            "target_id IS NULL AND reference_id IS NULL AND reference = ?",
+            params![reference],
+        )?;
+    }
+    resolve_links(conn, "target_id IS NULL AND reference_id = ?", params![note_id])?;
+    Ok(())
+}
+
+/// Resolves the links by id to `note_id` again, after the users it is shared with changed.
+pub(crate) fn relink_shared_note(conn: &Connection, note_id: Uuid) -> rusqlite::Result<()> {
+    resolve_links(conn, "reference_id = ?", params![note_id])?;
+    Ok(())
+}
+
+/// Notes the caller can read that link to the note.
+pub async fn find_backlinks(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<FindBacklinksResponse> {
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        require_access(conn, note_id, user_id, Access::Read)?;
+        let backlinks = conn
+            .prepare(
+                r#"SELECT notes.id, notes.title, note_links.reference FROM note_links
+                JOIN notes ON notes.id = note_links.source_id
+                WHERE note_links.target_id = ?1 AND notes.deleted_at IS NULL AND (
+                    notes.created_by = ?2
+                    OR EXISTS (SELECT 1 FROM note_shares WHERE note_id = notes.id AND user_id = ?2)
+                )
+                ORDER BY notes.id"#,
+            )?
+            .query_map(params![note_id, user_id], |row| {
+                Ok(Backlink {
+                    note_id: row.get(0)?,
+                    title: row.get(1)?,
+                    reference: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(FindBacklinksResponse { results: backlinks })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn get_graph(BaseParams { db, ctx }: BaseParams) -> Result<NoteGraph> {
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let nodes = conn
+            .prepare("SELECT id, title FROM notes WHERE created_by = ? AND deleted_at IS NULL ORDER BY id")?
+            .query_map(params![user_id], |row| {
+                Ok(GraphNode {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let edges = conn
+            .prepare(
+                r#"SELECT note_links.source_id, note_links.target_id FROM note_links
+                JOIN notes AS source ON source.id = note_links.source_id
+                JOIN notes AS target ON target.id = note_links.target_id
+                WHERE source.created_by = ?1 AND source.deleted_at IS NULL
+                    AND target.created_by = ?1 AND target.deleted_at IS NULL
+                ORDER BY note_links.source_id, note_links.target_id"#,
+            )?
+            .query_map(params![user_id], |row| {
+                Ok(GraphEdge {
+                    source_id: row.get(0)?,
+                    target_id: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let dangling = conn
+            .prepare(
+                r#"SELECT note_links.source_id, note_links.reference FROM note_links
+                JOIN notes AS source ON source.id = note_links.source_id
+                WHERE source.created_by = ? AND source.deleted_at IS NULL AND note_links.target_id IS NULL
+                ORDER BY note_links.source_id, note_links.reference"#,
+            )?
+            .query_map(params![user_id], |row| {
+                Ok(DanglingLink {
+                    source_id: row.get(0)?,
+                    reference: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(NoteGraph { nodes, edges, dangling })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_wiki_links() {
+        assert_eq!(
+            parse_links("See [[Plan]], [[ 018f6138-5b4f-722d-97c5-29b927cedbd4 ]] and [[]] or [[a [[b]] [[c\nd]]"),
+            ["Plan", "018f6138-5b4f-722d-97c5-29b927cedbd4", "b"]
+        );
+        assert!(parse_links("[[unclosed").is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+mod handlers;
+mod model;
+mod routes;
+
+pub(crate) use handlers::{relink_shared_note, update_links};
+
+use model::*;
+
+use crate::{openapi::aide::axum::ApiRouter, state::AppState};
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new().merge(routes::router(state.clone()))
+}
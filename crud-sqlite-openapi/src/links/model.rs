@@ -0,0 +1,44 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A note that links to another one.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Backlink {
+    pub note_id: Uuid,
+    pub title: String,
+    /// What the note wrote between `[[` and `]]`, a title or an id.
+    pub reference: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindBacklinksResponse {
+    pub results: Vec<Backlink>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GraphNode {
+    pub id: Uuid,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct GraphEdge {
+    pub source_id: Uuid,
+    pub target_id: Uuid,
+}
+
+/// A link that matches no note yet, it resolves once a note with that title or id is created.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct DanglingLink {
+    pub source_id: Uuid,
+    pub reference: String,
+}
+
+/// The caller's notes and the links between them, trashed notes are left out.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NoteGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    pub dangling: Vec<DanglingLink>,
+}
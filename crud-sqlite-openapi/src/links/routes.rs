@@ -0,0 +1,222 @@
+use crate::{
+    openapi::{
+        aide::axum::{routing::get_with, ApiRouter, IntoApiResponse},
+        Json, Path, TransformOperationExt,
+    },
+    policy::{policies::NotesRead, Authorized},
+    state::AppState,
+};
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::handlers;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NoteIdPath {
+    note_id: Uuid,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/notes/graph",
+            get_with(get_graph, |t| {
+                t.description("The caller's notes as nodes, and the `[[...]]` links between them as edges.")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/backlinks",
+            get_with(find_backlinks, |t| {
+                t.description("Notes the caller can read that link to the note with `[[title]]` or `[[id]]`.")
+                    .error::<404>("Note not found")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn get_graph(Authorized(base, _): Authorized<NotesRead>) -> impl IntoApiResponse {
+    handlers::get_graph(base).await.map(Json)
+}
+
+async fn find_backlinks(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_backlinks(note_id, base).await.map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        db::{init_test_db, DB},
+        errors::Result,
+        links::{DanglingLink, FindBacklinksResponse, GraphEdge, NoteGraph},
+        notes::Note,
+        state::AppState,
+    };
+
+    #[tokio::test]
+    async fn wiki_links() -> Result<()> {
+        let server = test_server(init_test_db().await?).await?;
+        let plan = create_note(&server, "Plan", "See [[Ideas]] and [[Missing]].").await;
+        let ideas = create_note(&server, "Ideas", "Back to [[plan]].").await;
+        let other = create_note(&server, "Other", &format!("By id: [[{ideas}]]")).await;
+
+        let backlinks = server
+            .get(&format!("/api/v1/notes/{ideas}/backlinks"))
+            .await
+            .json::<FindBacklinksResponse>();
+        let sources = backlinks
+            .results
+            .iter()
+            .map(|backlink| (backlink.note_id, backlink.reference.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(sources, [(plan, "Ideas"), (other, ideas.to_string().as_str())]);
+
+        let graph = server.get("/api/v1/notes/graph").await.json::<NoteGraph>();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(
+            graph.edges,
+            [
+                GraphEdge {
+                    source_id: plan,
+                    target_id: ideas
+                },
+                GraphEdge {
+                    source_id: ideas,
+                    target_id: plan
+                },
+                GraphEdge {
+                    source_id: other,
+                    target_id: ideas
+                },
+            ]
+        );
+        assert_eq!(
+            graph.dangling,
+            [DanglingLink {
+                source_id: plan,
+                reference: "Missing".into()
+            }]
+        );
+
+        // renamed, links by the former title dangle and the new title resolves
+        server
+            .patch(&format!("/api/v1/notes/{ideas}"))
+            .json(&json!({ "title": "Missing" }))
+            .await;
+        let graph = server.get("/api/v1/notes/graph").await.json::<NoteGraph>();
+        assert_eq!(
+            graph.dangling,
+            [DanglingLink {
+                source_id: plan,
+                reference: "Ideas".into()
+            }]
+        );
+        assert_eq!(graph.edges.len(), 3);
+
+        server.delete(&format!("/api/v1/notes/{other}")).await;
+        let backlinks = server
+            .get(&format!("/api/v1/notes/{ideas}/backlinks"))
+            .await
+            .json::<FindBacklinksResponse>();
+        assert_eq!(backlinks.results.len(), 1);
+
+        server
+            .get("/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4/backlinks")
+            .expect_failure()
+            .await
+            .assert_status_not_found();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn links_to_unreadable_notes_dangle() -> Result<()> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'Secret', '', uuid_blob('018f6146-32f4-7948-8289-000000000002'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db.clone()).await?;
+        let secret = "018f6138-5b4f-722d-97c5-29b927cedbd4";
+        let source = create_note(&server, "Source", &format!("[[{secret}]] and [[Secret]]")).await;
+        let dangling = |references: &[&str]| {
+            references
+                .iter()
+                .map(|reference| DanglingLink {
+                    source_id: source,
+                    reference: reference.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let graph = server.get("/api/v1/notes/graph").await.json::<NoteGraph>();
+        assert_eq!(graph.dangling, dangling(&[secret, "Secret"]));
+
+        // shared, the link by id resolves, the one by title still only finds the caller's notes
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO note_shares (note_id, user_id, permission) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'read');
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        server
+            .patch(&format!("/api/v1/notes/{source}"))
+            .json(&json!({ "text": format!("[[{secret}]] and [[Secret]].") }))
+            .await;
+        let backlinks = server
+            .get(&format!("/api/v1/notes/{secret}/backlinks"))
+            .await
+            .json::<FindBacklinksResponse>();
+        assert_eq!(backlinks.results.len(), 1);
+        let graph = server.get("/api/v1/notes/graph").await.json::<NoteGraph>();
+        assert_eq!(graph.dangling, dangling(&["Secret"]));
+
+        // leaving the note dangles the link again
+        server
+            .delete(&format!(
+                "/api/v1/notes/{secret}/shares/018f6146-32f4-7948-8289-cfb5cdb2b2af"
+            ))
+            .await;
+        let graph = server.get("/api/v1/notes/graph").await.json::<NoteGraph>();
+        assert_eq!(graph.dangling, dangling(&[secret, "Secret"]));
+        Ok(())
+    }
+
+    async fn create_note(server: &TestServer, title: &str, text: &str) -> Uuid {
+        server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": title, "text": text }))
+            .await
+            .json::<Note>()
+            .id
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, |state: AppState| {
+            super::router(state.clone()).merge(crate::notes::router(state))
+        })
+        .await
+    }
+}
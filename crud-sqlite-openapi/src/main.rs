@@ -1,19 +1,30 @@
 mod config;
 
 mod app;
+mod attachments;
+mod auth;
 mod ctx;
 mod db;
 mod errors;
+mod etag;
+mod links;
+mod notebooks;
 mod notes;
 mod openapi;
+mod policy;
+mod reminders;
 mod state;
+mod tags;
+mod transfer;
+mod users;
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
 use aide::axum::ApiRouter;
 use app::AppParams;
+use auth::jwt::Keys;
 use axum::body::Body;
-pub use config::config;
+use config::Config;
 pub use db::{init_db, DB};
 pub use errors::{Error, Result};
 use tokio::net::TcpListener;
@@ -23,7 +34,8 @@ use tracing_subscriber::prelude::*;
 
 #[tokio::main]
 async fn main() -> errors::Result<()> {
-    let config = config();
+    let config = Config::from_env();
+    let keys = Keys::new(&config.jwt_keys)?;
 
     tracing_subscriber::registry()
         .with(
@@ -42,11 +54,28 @@ async fn main() -> errors::Result<()> {
         .try_init()
         .ok();
 
-    let conn = init_db().await?;
+    let conn = init_db(&config.database_url).await?;
+    notes::spawn_trash_purge(conn.clone(), config.trash_retention_days);
+    attachments::spawn_upload_purge(conn.clone());
+    reminders::spawn_reminders(conn.clone(), reminders::SystemClock);
 
+    let port = config.port;
     let (app, api) = app::create(AppParams {
         db: conn,
-        router: |state| ApiRouter::new().merge(notes::router(state)),
+        config: Arc::new(config),
+        keys: Arc::new(keys),
+        router: |state| {
+            ApiRouter::new()
+                .merge(attachments::router(state.clone()))
+                .merge(links::router(state.clone()))
+                .merge(auth::router(state.clone()))
+                .merge(notebooks::router(state.clone()))
+                .merge(notes::router(state.clone()))
+                .merge(reminders::router(state.clone()))
+                .merge(tags::router(state.clone()))
+                .merge(transfer::router(state.clone()))
+                .merge(users::router(state))
+        },
     })
     .await?;
 
@@ -75,7 +104,6 @@ async fn main() -> errors::Result<()> {
         ),
     );
 
-    let port = config.port;
     let listener = TcpListener::bind(format!("127.0.0.1:{port}")).await.unwrap();
 
     tracing::info!("listening on http://{}", listener.local_addr().unwrap());
@@ -89,26 +117,57 @@ async fn main() -> errors::Result<()> {
 
 #[cfg(test)]
 pub mod tests {
+    use std::sync::Arc;
+
     use crate::{
         app::{create, AppParams},
-        config::config_override,
+        auth::{self, jwt::Keys},
+        config::{Config, JwtKey},
         errors::Result,
         state::AppState,
         DB,
     };
     use aide::axum::ApiRouter;
+    use axum::http::header;
     use axum_test::{TestServer, TestServerConfig};
+    use uuid::{uuid, Uuid};
+
+    /// Admin user inserted by the dev fixtures migration.
+    pub const TEST_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-cfb5cdb2b2af");
+
+    /// The config read from the environment, with a fixed JWT signing key.
+    pub fn test_config() -> Config {
+        Config {
+            jwt_keys: vec![JwtKey {
+                kid: "test".into(),
+                private_key: "bSrkAIbeZ8EzkfTMx4UQ3EjBMmd_E0VSFWLmV3Ck9bY".into(),
+            }],
+            ..Config::from_env()
+        }
+    }
 
+    /// Test server authenticated as [`TEST_USER_ID`].
     pub async fn test_server<R>(db: DB, router: R) -> Result<TestServer>
     where
         R: FnOnce(AppState) -> ApiRouter,
     {
-        config_override(|mut config| {
-            // TODO
-            config
-        });
+        test_server_with_config(db, test_config(), router).await
+    }
 
-        let (app, _) = create(AppParams { db, router }).await?;
+    /// Like [`test_server`], with the given config.
+    pub async fn test_server_with_config<R>(db: DB, config: Config, router: R) -> Result<TestServer>
+    where
+        R: FnOnce(AppState) -> ApiRouter,
+    {
+        let keys = Keys::new(&config.jwt_keys)?;
+        let session_ttl_seconds = config.session_ttl_seconds;
+        let (app, _) = create(AppParams {
+            db: db.clone(),
+            config: Arc::new(config),
+            keys: Arc::new(keys),
+            router,
+        })
+        .await?;
 
         let config = TestServerConfig {
             save_cookies: true,
@@ -117,6 +176,19 @@ pub mod tests {
             ..TestServerConfig::default()
         };
 
-        Ok(TestServer::new_with_config(app, config).unwrap())
+        let mut server = TestServer::new_with_config(app, config).unwrap();
+        let token = auth::create_session(&db, TEST_USER_ID, None, session_ttl_seconds).await?;
+        server.add_header(header::AUTHORIZATION, format!("Bearer {token}"));
+
+        Ok(server)
+    }
+
+    /// Replaces the server's default headers with a bearer token of a new session for `user_id`.
+    pub async fn authenticate(db: &DB, server: &mut TestServer, user_id: Uuid) -> Result<()> {
+        let token = auth::create_session(db, user_id, None, test_config().session_ttl_seconds).await?;
+
+        server.clear_headers();
+        server.add_header(header::AUTHORIZATION, format!("Bearer {token}"));
+        Ok(())
     }
 }
@@ -0,0 +1,179 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use crate::{ctx::BaseParams, db, notes::load_details, notes::Note, Error, Result};
+
+use super::{CreateNotebook, FindNotebooksResponse, MoveNotebook, Notebook, NotebookSubtree, SubtreeNotebook};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+impl<'a> TryFrom<&Row<'a>> for Notebook {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            parent_id: row.get(1)?,
+            name: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    }
+}
+
+/// `NotFound` unless the notebook belongs to `user_id`.
+fn require_notebook(
+    conn: &Connection,
+    notebook_id: Uuid,
+    user_id: Option<Uuid>,
+) -> std::result::Result<(), tokio_rusqlite::Error> {
+    conn.query_row(
+        "SELECT 1 FROM notebooks WHERE id = ? AND user_id = ?",
+        params![notebook_id, user_id],
+        |_| Ok(()),
+    )
+    .optional()?
+    .ok_or_else(|| Error::NotFound("Notebook not found".into()).into())
+}
+
+pub async fn find_notebooks(BaseParams { db, ctx }: BaseParams) -> Result<FindNotebooksResponse> {
+    db.call(move |conn| {
+        let notebooks = conn
+            .prepare("SELECT id, parent_id, name, created_at FROM notebooks WHERE user_id = ? ORDER BY name, id")?
+            .query_map(params![ctx.get_user_id()], |row| Notebook::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindNotebooksResponse { results: notebooks })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn create_notebook(
+    CreateNotebook { name, parent_id }: CreateNotebook,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Notebook> {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(Error::Validation(format!(
+            "Notebook names must be 1 to {MAX_NAME_LENGTH} characters long"
+        )));
+    }
+
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        if let Some(parent_id) = parent_id {
+            require_notebook(conn, parent_id, user_id)?;
+        }
+        conn.query_row(
+            r#"INSERT INTO notebooks (user_id, parent_id, name) VALUES (?, ?, ?)
+            RETURNING id, parent_id, name, created_at"#,
+            params![user_id, parent_id, name],
+            |row| Notebook::try_from(row),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Moves a notebook, along with its subtree, under another notebook or to the top level.
+pub async fn move_notebook(
+    notebook_id: Uuid,
+    MoveNotebook { parent_id }: MoveNotebook,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Notebook> {
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let tx = conn.transaction()?;
+        require_notebook(&tx, notebook_id, user_id)?;
+
+        if let Some(parent_id) = parent_id {
+            require_notebook(&tx, parent_id, user_id)?;
+
+            // the new parent can't be the notebook itself or one of its descendants
+            let cycle = tx
+                .query_row(
+                    r#"WITH RECURSIVE subtree (id) AS (
+                        SELECT ?1
+                        UNION
+                        SELECT notebooks.id FROM notebooks JOIN subtree ON notebooks.parent_id = subtree.id
+                    )
+                    SELECT 1 FROM subtree WHERE id = ?2"#,
+                    params![notebook_id, parent_id],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            if cycle.is_some() {
+                return Err(
+                    Error::Validation("Can't move a notebook into itself or one of its notebooks".into()).into(),
+                );
+            }
+        }
+
+        let notebook = tx.query_row(
+            r#"UPDATE notebooks SET parent_id = ?
+            WHERE id = ?
+            RETURNING id, parent_id, name, created_at"#,
+            params![parent_id, notebook_id],
+            |row| Notebook::try_from(row),
+        )?;
+        // the breadcrumbs of the notes in the subtree changed
+        tx.execute(
+            r#"WITH RECURSIVE subtree (id) AS (
+                SELECT ?
+                UNION
+                SELECT notebooks.id FROM notebooks JOIN subtree ON notebooks.parent_id = subtree.id
+            )
+            UPDATE notes SET version = version + 1 WHERE notebook_id IN (SELECT id FROM subtree)"#,
+            params![notebook_id],
+        )?;
+        tx.commit()?;
+        Ok(notebook)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// The notebook, its descendants and the notes in all of them.
+pub async fn find_subtree(notebook_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<NotebookSubtree> {
+    db.call(move |conn| {
+        require_notebook(conn, notebook_id, ctx.get_user_id())?;
+
+        let subtree = r#"WITH RECURSIVE subtree (id, parent_id, name, created_at, depth) AS (
+            SELECT id, parent_id, name, created_at, 0 FROM notebooks WHERE id = ?
+            UNION ALL
+            SELECT notebooks.id, notebooks.parent_id, notebooks.name, notebooks.created_at, subtree.depth + 1
+            FROM notebooks JOIN subtree ON notebooks.parent_id = subtree.id
+        )"#;
+
+        let notebooks = conn
+            .prepare(&format!(
+                "{subtree} SELECT id, parent_id, name, created_at, depth FROM subtree ORDER BY depth, name, id"
+            ))?
+            .query_map(params![notebook_id], |row| {
+                Ok(SubtreeNotebook {
+                    notebook: Notebook::try_from(row)?,
+                    depth: row.get(4)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut notes = conn
+            .prepare(&format!(
+                r#"{subtree} SELECT notes.id, title, text, notes.created_at, created_by, updated_at, updated_by
+                FROM notes JOIN subtree ON notes.notebook_id = subtree.id
+                WHERE notes.deleted_at IS NULL
+                ORDER BY notes.id DESC"#
+            ))?
+            .query_map(params![notebook_id], |row| Note::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        load_details(conn, notes.iter_mut())?;
+
+        Ok(NotebookSubtree { notebooks, notes })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
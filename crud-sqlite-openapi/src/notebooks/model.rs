@@ -0,0 +1,48 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::notes::Note;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Notebook {
+    pub id: Uuid,
+    /// Unset at the top level.
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateNotebook {
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveNotebook {
+    /// New parent, the top level when unset.
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindNotebooksResponse {
+    /// In alphabetical order.
+    pub results: Vec<Notebook>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SubtreeNotebook {
+    #[serde(flatten)]
+    pub notebook: Notebook,
+    /// 0 for the root of the subtree.
+    pub depth: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NotebookSubtree {
+    /// The notebook and its descendants, level by level.
+    pub notebooks: Vec<SubtreeNotebook>,
+    /// Notes in any of `notebooks`, newest first.
+    pub notes: Vec<Note>,
+}
@@ -0,0 +1,176 @@
+use crate::{
+    openapi::{
+        aide::axum::{
+            routing::{get, get_with, post_with},
+            ApiRouter, IntoApiResponse,
+        },
+        Json, Path, TransformOperationExt,
+    },
+    policy::{
+        policies::{NotesRead, NotesWrite},
+        Authorized,
+    },
+    state::AppState,
+};
+use axum::http::StatusCode;
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{handlers, CreateNotebook, MoveNotebook, Notebook};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NotebookIdPath {
+    notebook_id: Uuid,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/notebooks",
+            get(find_notebooks).post_with(create_notebook, |t| {
+                t.response::<201, Json<Notebook>>()
+                    .error::<400>("Invalid name")
+                    .error::<404>("Parent not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notebooks/{notebook_id}/move",
+            post_with(move_notebook, |t| {
+                t.description("Moves the notebook and everything in it.")
+                    .error::<400>("Moving a notebook into itself or one of its notebooks")
+                    .error::<404>("Notebook or parent not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notebooks/{notebook_id}/tree",
+            get_with(find_subtree, |t| {
+                t.description("The notebook, all notebooks nested in it and their notes.")
+                    .error::<404>("Notebook not found")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn find_notebooks(Authorized(base, _): Authorized<NotesRead>) -> impl IntoApiResponse {
+    handlers::find_notebooks(base).await.map(Json)
+}
+
+async fn create_notebook(
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<CreateNotebook>,
+) -> impl IntoApiResponse {
+    handlers::create_notebook(args, base)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+async fn move_notebook(
+    Path(NotebookIdPath { notebook_id }): Path<NotebookIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<MoveNotebook>,
+) -> impl IntoApiResponse {
+    handlers::move_notebook(notebook_id, args, base).await.map(Json)
+}
+
+async fn find_subtree(
+    Path(NotebookIdPath { notebook_id }): Path<NotebookIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_subtree(notebook_id, base).await.map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+    use serde_json::json;
+
+    use crate::{
+        db::{init_test_db, DB},
+        errors::Result,
+        notebooks::{Notebook, NotebookSubtree},
+        notes::Note,
+    };
+
+    #[tokio::test]
+    async fn nested_notebooks() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+        let notes = crate::tests::test_server(db, crate::notes::router).await?;
+
+        let create = |name: &str, parent_id: Option<uuid::Uuid>| {
+            let request = server
+                .post("/api/v1/notebooks")
+                .json(&json!({ "name": name, "parent_id": parent_id }));
+            async { request.await.json::<Notebook>() }
+        };
+        let work = create("work", None).await;
+        let projects = create("projects", Some(work.id)).await;
+        let api = create("api", Some(projects.id)).await;
+
+        let note = notes
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "endpoints", "text": "" }))
+            .await
+            .json::<Note>();
+        let note = notes
+            .post(&format!("/api/v1/notes/{}/move", note.id))
+            .json(&json!({ "notebook_id": api.id }))
+            .await
+            .json::<Note>();
+        assert_eq!(note.notebook_id, Some(api.id));
+        let path: Vec<_> = note.breadcrumbs.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(path, ["work", "projects", "api"]);
+
+        let tree = server
+            .get(&format!("/api/v1/notebooks/{}/tree", work.id))
+            .await
+            .json::<NotebookSubtree>();
+        let levels: Vec<_> = tree
+            .notebooks
+            .iter()
+            .map(|n| (n.notebook.name.as_str(), n.depth))
+            .collect();
+        assert_eq!(levels, [("work", 0), ("projects", 1), ("api", 2)]);
+        assert_eq!(tree.notes.len(), 1);
+        assert_eq!(tree.notes[0].breadcrumbs.len(), 3);
+
+        // no cycles
+        for parent in [work.id, api.id] {
+            let response = server
+                .post(&format!("/api/v1/notebooks/{}/move", work.id))
+                .json(&json!({ "parent_id": parent }))
+                .expect_failure()
+                .await;
+            assert_eq!(response.status_code(), 400);
+        }
+
+        let url = format!("/api/v1/notes/{}", note.id);
+        let etag = notes.get(&url).await.header("etag");
+        let moved = server
+            .post(&format!("/api/v1/notebooks/{}/move", api.id))
+            .json(&json!({ "parent_id": null }))
+            .await
+            .json::<Notebook>();
+        assert!(moved.parent_id.is_none());
+        // the breadcrumbs changed, so did the version
+        let response = notes.get(&url).add_header("if-none-match", etag.clone()).await;
+        assert_eq!(response.status_code(), 200);
+        assert_ne!(response.header("etag"), etag);
+        assert_eq!(response.json::<Note>().breadcrumbs.len(), 1);
+
+        let tree = server
+            .get(&format!("/api/v1/notebooks/{}/tree", work.id))
+            .await
+            .json::<NotebookSubtree>();
+        assert_eq!(tree.notebooks.len(), 2);
+        assert!(tree.notes.is_empty());
+        Ok(())
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, super::router).await
+    }
+}
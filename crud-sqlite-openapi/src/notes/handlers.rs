@@ -1,13 +1,50 @@
-use rusqlite::{params, Row};
-use sea_query::{Iden, Query, SqliteQueryBuilder};
+use std::{collections::HashSet, str::FromStr};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use lazy_static::lazy_static;
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+    Connection, OptionalExtension, Row, ToSql,
+};
+use sea_query::{
+    Asterisk, Cond, Expr, Func, Iden, JoinType, LikeExpr, Order, Query, SimpleExpr, SqliteQueryBuilder, Value,
+};
+use sea_query_rusqlite::{RusqliteBinder, RusqliteValues};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
 use uuid::Uuid;
 
-use crate::{ctx::BaseParams, db, Error, Result};
+use crate::{
+    auth::normalize_email,
+    ctx::BaseParams,
+    db,
+    errors::ErrorResponse,
+    etag::{EntityTag, Precondition},
+    links::{relink_shared_note, update_links},
+    reminders,
+    tags::normalize_tag,
+    Error, Result, DB,
+};
 
-use super::{CreateNote, FindNotesResponse, UpdateNote};
+use super::{
+    BatchNotes, BatchNotesResponse, BatchOperation, BatchResult, Breadcrumb, Collaborator, CreateNote, DiffLine,
+    DiffOp, DiffRevisions, FindCollaboratorsResponse, FindNotes, FindNotesResponse, FindRevisionsResponse,
+    FindSharedNotesResponse, FindTrashResponse, GetNote, MergePatchNote, MoveNote, NoteDiff, NotePatch, NoteRevision,
+    NoteSearchResult, NoteSort, RenderedNote, SearchNotes, SearchNotesResponse, ShareNote, SharePermission, SharedNote,
+    SortOrder, TagMatch, TrashedNote, UpdateNote,
+};
 
 use super::{Note, UpdateNoteForm};
 
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 200;
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+const MAX_BATCH_OPERATIONS: usize = 1000;
+
+#[derive(Iden)]
+struct Datetime;
+
 #[derive(Iden)]
 pub enum Notes {
     Table,
@@ -18,6 +55,41 @@ pub enum Notes {
     CreatedAt,
     UpdatedBy,
     UpdatedAt,
+    DeletedAt,
+}
+
+/// FTS5 index over the title and text of notes, see the migrations.
+#[derive(Iden)]
+pub enum NotesFts {
+    Table,
+}
+
+#[derive(Iden)]
+pub enum NoteSearchIds {
+    Table,
+    SearchId,
+    NoteId,
+}
+
+#[derive(Iden)]
+pub enum NoteShares {
+    Table,
+    NoteId,
+    UserId,
+}
+
+#[derive(Iden)]
+pub enum Tags {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+pub enum NoteTags {
+    Table,
+    NoteId,
+    TagId,
 }
 
 impl<'a> TryFrom<&Row<'a>> for Note {
@@ -32,40 +104,503 @@ impl<'a> TryFrom<&Row<'a>> for Note {
             created_by: row.get(4)?,
             updated_at: row.get(5)?,
             updated_by: row.get(6)?,
+            version: 0,
+            due_at: None,
+            remind_at: None,
+            tags: Vec::new(),
+            notebook_id: None,
+            breadcrumbs: Vec::new(),
+            html: None,
+        })
+    }
+}
+
+impl<'a> TryFrom<&Row<'a>> for NoteRevision {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            note_id: row.get(0)?,
+            revision: row.get(1)?,
+            title: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            text: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            updated_at: row.get(4)?,
+            updated_by: row.get(5)?,
+        })
+    }
+}
+
+impl SharePermission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+impl FromStr for SharePermission {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            _ => Err(Error::Unexpected(format!("Unknown share permission: {s}"))),
+        }
+    }
+}
+
+impl FromSql for SharePermission {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        SharePermission::from_str(value.as_str()?).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for SharePermission {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl<'a> TryFrom<&Row<'a>> for Collaborator {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: row.get(0)?,
+            email: row.get(1)?,
+            permission: row.get(2)?,
+            created_at: row.get(3)?,
         })
     }
 }
 
-pub async fn find_notes(BaseParams { db, ctx }: BaseParams) -> Result<FindNotesResponse> {
+/// What a user may do with a note, ordered from least to most.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Access {
+    Read,
+    Write,
+    Owner,
+}
+
+/// Access of `user_id` to a note, `None` when the note doesn't exist, is in the trash or isn't shared with them.
+fn access(conn: &Connection, note_id: Uuid, user_id: Option<Uuid>) -> rusqlite::Result<Option<Access>> {
+    let found = conn
+        .query_row(
+            r#"SELECT notes.created_by = ?2, note_shares.permission FROM notes
+            LEFT JOIN note_shares ON note_shares.note_id = notes.id AND note_shares.user_id = ?2
+            WHERE notes.id = ?1 AND notes.deleted_at IS NULL"#,
+            params![note_id, user_id],
+            |row| {
+                Ok((
+                    row.get::<_, Option<bool>>(0)?,
+                    row.get::<_, Option<SharePermission>>(1)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    Ok(match found {
+        Some((Some(true), _)) => Some(Access::Owner),
+        Some((_, Some(SharePermission::Write))) => Some(Access::Write),
+        Some((_, Some(SharePermission::Read))) => Some(Access::Read),
+        _ => None,
+    })
+}
+
+/// Fails with `NotFound` for notes the user can't see and `Forbidden` when they may do less than `required`.
+pub(crate) fn require_access(
+    conn: &Connection,
+    note_id: Uuid,
+    user_id: Option<Uuid>,
+    required: Access,
+) -> std::result::Result<Access, tokio_rusqlite::Error> {
+    match access(conn, note_id, user_id)? {
+        None => Err(Error::NotFound("Note not found".into()).into()),
+        Some(access) if access < required => Err(Error::Forbidden.into()),
+        Some(access) => Ok(access),
+    }
+}
+
+/// Fails with `PreconditionFailed` when the note changed since the versions of `if_match`.
+fn require_version(
+    conn: &Connection,
+    note_id: Uuid,
+    if_match: Option<&Precondition>,
+) -> std::result::Result<(), tokio_rusqlite::Error> {
+    let Some(if_match) = if_match else {
+        return Ok(());
+    };
+    let version = conn.query_row("SELECT version FROM notes WHERE id = ?", params![note_id], |row| {
+        row.get(0)
+    })?;
+    if !if_match.matches(version) {
+        return Err(Error::PreconditionFailed(format!("Note changed, its current version is {version}")).into());
+    }
+    Ok(())
+}
+
+pub async fn find_notes(args: FindNotes, BaseParams { db, ctx }: BaseParams) -> Result<FindNotesResponse> {
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(Error::Validation(format!("Limit must be between 1 and {MAX_LIMIT}")));
+    }
+    let sort = args.sort;
+    let after = args
+        .cursor
+        .as_deref()
+        .map(|cursor| Cursor::decode(cursor, sort))
+        .transpose()?;
+    let has_key = sort_key(sort).is_some();
+    let html = args.html;
+
+    let ((sql, values), count) = find_notes_queries(args, limit, after, ctx.get_user_id());
+
     db.call(move |conn| {
-        let (sql, values) = Query::select()
-            .columns({
-                use Notes::*;
-                [Id, Title, Text, CreatedBy, CreatedAt, UpdatedBy, UpdatedAt]
+        let mut notes = conn
+            .prepare(&sql)?
+            .query_map(&*values.as_params(), |row| {
+                let key = if has_key { row.get(7)? } else { None };
+                Ok((Note::try_from(row)?, key))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let next_cursor = if notes.len() > limit as usize {
+            notes.truncate(limit as usize);
+            notes.last().map(|(note, key)| {
+                Cursor {
+                    sort,
+                    key: key.clone(),
+                    id: note.id,
+                }
+                .encode()
             })
+        } else {
+            None
+        };
+
+        let total = count
+            .map(|(sql, values)| conn.query_row(&sql, &*values.as_params(), |row| row.get(0)))
+            .transpose()?;
+        load_details(conn, notes.iter_mut().map(|(note, _)| note))?;
+        if html {
+            load_html(conn, notes.iter_mut().map(|(note, _)| note))?;
+        }
+
+        Ok(FindNotesResponse {
+            results: notes.into_iter().map(|(note, _)| note).collect(),
+            next_cursor,
+            total,
+        })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Builds the page query and, when requested, the count query. sea-query statements aren't `Send`,
+/// so only their SQL moves to the connection thread.
+fn find_notes_queries(
+    FindNotes {
+        title,
+        created_by,
+        created_after,
+        created_before,
+        updated_after,
+        updated_before,
+        tags,
+        tag_match,
+        sort,
+        order,
+        with_total,
+        ..
+    }: FindNotes,
+    limit: u32,
+    after: Option<Cursor>,
+    user_id: Option<Uuid>,
+) -> ((String, RusqliteValues), Option<(String, RusqliteValues)>) {
+    let filter = Cond::all()
+        .add(readable_by(user_id))
+        .add_option(title.map(|title| {
+            Expr::col(Notes::Title).like(LikeExpr::new(format!("%{}%", escape_like(&title))).escape('\\'))
+        }))
+        .add_option(created_by.map(|user_id| Expr::col(Notes::CreatedBy).eq(user_id)))
+        .add_option(created_after.map(|date| datetime(Expr::col(Notes::CreatedAt)).gte(datetime(date))))
+        .add_option(created_before.map(|date| datetime(Expr::col(Notes::CreatedAt)).lt(datetime(date))))
+        .add_option(updated_after.map(|date| datetime(Expr::col(Notes::UpdatedAt)).gte(datetime(date))))
+        .add_option(updated_before.map(|date| datetime(Expr::col(Notes::UpdatedAt)).lt(datetime(date))))
+        .add_option(tags.map(|tags| {
+            let names: Vec<_> = tags.split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+            match tag_match {
+                TagMatch::All => names
+                    .into_iter()
+                    .fold(Cond::all(), |cond, name| cond.add(tagged_with([name]))),
+                TagMatch::Any => Cond::all().add(tagged_with(names)),
+            }
+        }));
+
+    let key = sort_key(sort);
+    let order = match order {
+        SortOrder::Asc => Order::Asc,
+        SortOrder::Desc => Order::Desc,
+    };
+    let past = |expr: SimpleExpr, value: Value| match order {
+        Order::Desc => Expr::expr(expr).lt(value),
+        _ => Expr::expr(expr).gt(value),
+    };
+
+    // keyset pagination: strictly past the cursor's sort key, ties broken by id
+    let keyset = after.map(|cursor| {
+        let id = past(Expr::col(Notes::Id).into(), cursor.id.into());
+        match (&key, cursor.key) {
+            (Some(key), Some(value)) => Cond::any()
+                .add(past(key.clone(), value.clone().into()))
+                .add(Cond::all().add(Expr::expr(key.clone()).eq(value)).add(id)),
+            _ => Cond::all().add(id),
+        }
+    });
+
+    let mut query = Query::select();
+    query
+        .columns({
+            use Notes::*;
+            [Id, Title, Text, CreatedAt, CreatedBy, UpdatedAt, UpdatedBy]
+        })
+        .from(Notes::Table)
+        .cond_where(filter.clone().add_option(keyset));
+    if let Some(key) = &key {
+        query.expr(key.clone()).order_by_expr(key.clone(), order.clone());
+    }
+    // one extra row tells whether there is a next page
+    query.order_by(Notes::Id, order).limit(limit as u64 + 1);
+
+    let count = with_total.then(|| {
+        Query::select()
+            .expr(Expr::col(Asterisk).count())
             .from(Notes::Table)
-            .build(SqliteQueryBuilder);
+            .cond_where(filter)
+            .build_rusqlite(SqliteQueryBuilder)
+    });
+
+    (query.build_rusqlite(SqliteQueryBuilder), count)
+}
+
+/// Notes owned by or shared with `user_id`, leaving out the trash.
+fn readable_by(user_id: Option<Uuid>) -> Cond {
+    Cond::all()
+        .add(Expr::col((Notes::Table, Notes::DeletedAt)).is_null())
+        .add(
+            Cond::any()
+                .add(Expr::col((Notes::Table, Notes::CreatedBy)).eq(user_id))
+                .add(Expr::exists(
+                    Query::select()
+                        .expr(Expr::val(1))
+                        .from(NoteShares::Table)
+                        .and_where(Expr::col((NoteShares::Table, NoteShares::NoteId)).equals((Notes::Table, Notes::Id)))
+                        .and_where(Expr::col((NoteShares::Table, NoteShares::UserId)).eq(user_id))
+                        .to_owned(),
+                )),
+        )
+}
+
+/// Notes with any of the tags `names`.
+fn tagged_with<'a>(names: impl IntoIterator<Item = &'a str>) -> SimpleExpr {
+    Expr::exists(
+        Query::select()
+            .expr(Expr::val(1))
+            .from(NoteTags::Table)
+            .inner_join(
+                Tags::Table,
+                Expr::col((Tags::Table, Tags::Id)).equals((NoteTags::Table, NoteTags::TagId)),
+            )
+            .and_where(Expr::col((NoteTags::Table, NoteTags::NoteId)).equals((Notes::Table, Notes::Id)))
+            .and_where(Expr::col((Tags::Table, Tags::Name)).is_in(names))
+            .to_owned(),
+    )
+}
+
+/// `datetime(expr)`, so that `CURRENT_TIMESTAMP` defaults and RFC 3339 values compare as dates, not strings.
+fn datetime(expr: impl Into<SimpleExpr>) -> Expr {
+    Expr::expr(Func::cust(Datetime).arg(expr))
+}
+
+/// Expression notes are sorted by before their id, `None` when sorting by id alone.
+fn sort_key(sort: NoteSort) -> Option<SimpleExpr> {
+    match sort {
+        // ids are time-ordered UUIDv7s
+        NoteSort::CreatedAt => None,
+        NoteSort::UpdatedAt => {
+            let updated_at = Func::coalesce([Expr::col(Notes::UpdatedAt).into(), Expr::col(Notes::CreatedAt).into()]);
+            Some(datetime(updated_at).into())
+        }
+        NoteSort::Title => Some(Func::coalesce([Expr::col(Notes::Title).into(), Expr::val("").into()]).into()),
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
 
-        let notes = conn.prepare(
-            "SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes WHERE created_by = ?",
-        )?.query_map(params![ctx.get_user_id()], |row| Note::try_from(row))?
-        .collect::<std::result::Result<Vec<_>, _>>()?;
-        Ok(FindNotesResponse { results: notes })
+/// Position after the last note of a page, handed out base64url encoded.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: NoteSort,
+    key: Option<String>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str, sort: NoteSort) -> Result<Self> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Self>(&json).ok())
+            .filter(|cursor| cursor.sort == sort)
+            .ok_or_else(|| Error::Validation("Invalid cursor".into()))
+    }
+}
+
+/// Ranks the notes the caller can read by relevance, title matches weighing more than text ones.
+pub async fn search_notes(
+    SearchNotes { q, limit }: SearchNotes,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<SearchNotesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(Error::Validation(format!("Limit must be between 1 and {MAX_LIMIT}")));
+    }
+    let query = match_query(&q).ok_or_else(|| Error::Validation("Empty search query".into()))?;
+
+    let (sql, values) = Query::select()
+        .columns(
+            {
+                use Notes::*;
+                [Id, Title, Text, CreatedAt, CreatedBy, UpdatedAt, UpdatedBy]
+            }
+            .map(|column| (Notes::Table, column)),
+        )
+        .expr(Expr::cust(format!(
+            "highlight(notes_fts, 0, '{MARK_START}', '{MARK_END}')"
+        )))
+        .expr(Expr::cust(format!(
+            "snippet(notes_fts, 1, '{MARK_START}', '{MARK_END}', '…', 16)"
+        )))
+        .expr(Expr::cust("bm25(notes_fts, 10.0, 1.0)"))
+        .from(Notes::Table)
+        .join(
+            JoinType::InnerJoin,
+            NoteSearchIds::Table,
+            Expr::col((NoteSearchIds::Table, NoteSearchIds::NoteId)).equals((Notes::Table, Notes::Id)),
+        )
+        .join(
+            JoinType::InnerJoin,
+            NotesFts::Table,
+            Expr::cust("notes_fts.rowid = note_search_ids.search_id"),
+        )
+        .cond_where(
+            Cond::all()
+                .add(Expr::cust_with_values("notes_fts MATCH ?", [query]))
+                .add(readable_by(ctx.get_user_id())),
+        )
+        .order_by_expr(Expr::cust("bm25(notes_fts, 10.0, 1.0)"), Order::Asc)
+        .order_by((Notes::Table, Notes::Id), Order::Desc)
+        .limit(limit as u64)
+        .build_rusqlite(SqliteQueryBuilder);
+
+    db.call(move |conn| {
+        let mut results = conn
+            .prepare(&sql)?
+            .query_map(&*values.as_params(), |row| {
+                Ok(NoteSearchResult {
+                    note: Note::try_from(row)?,
+                    title_snippet: mark_matches(&row.get::<_, Option<String>>(7)?.unwrap_or_default()),
+                    text_snippet: mark_matches(&row.get::<_, Option<String>>(8)?.unwrap_or_default()),
+                    rank: row.get(9)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        load_details(conn, results.iter_mut().map(|result| &mut result.note))?;
+
+        Ok(SearchNotesResponse { results })
     })
     .await
     .map_err(db::Error::from)
     .map_err(Error::from)
 }
 
-pub async fn create_note(CreateNote { title, text }: CreateNote, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
+/// Starts a match in `highlight()` and `snippet()`. A private use character, so that it only becomes
+/// a `<mark>` tag once the note's own text is escaped.
+const MARK_START: char = '\u{E000}';
+/// Ends a match, see [`MARK_START`].
+const MARK_END: char = '\u{E001}';
+
+/// HTML-escapes a highlighted title or snippet, then turns the match markers into `<mark>` tags.
+fn mark_matches(highlighted: &str) -> String {
+    let mut html = String::with_capacity(highlighted.len());
+    for c in highlighted.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Turns user input into an FTS5 query matching all of its terms, so that FTS5 operators and
+/// column filters in it are searched for rather than interpreted. `"quoted words"` stay a phrase
+/// and a trailing `*` makes a term, or phrase, a prefix. `None` when there is nothing to search.
+fn match_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() {
+        let (term, prefix, tail) = match rest.strip_prefix('"') {
+            Some(phrase) => {
+                let (term, tail) = phrase.split_once('"').unwrap_or((phrase, ""));
+                match tail.strip_prefix('*') {
+                    Some(tail) => (term, true, tail),
+                    None => (term, false, tail),
+                }
+            }
+            None => {
+                let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                match word.strip_suffix('*') {
+                    Some(word) => (word, true, tail),
+                    None => (word, false, tail),
+                }
+            }
+        };
+
+        if !term.trim().is_empty() {
+            let star = if prefix { "*" } else { "" };
+            terms.push(format!("\"{}\"{star}", term.replace('"', "\"\"")));
+        }
+        rest = tail.trim_start();
+    }
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+pub async fn create_note(args: CreateNote, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
     db.call(move |conn| {
-        conn.query_row(
-            r#"INSERT INTO notes (title, text, created_by) VALUES (?, ?, ?)
-            RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
-            params![title, text, ctx.get_user_id()],
-            |row| Note::try_from(row),
-        )
-        .map_err(|e| e.into())
+        let tx = conn.transaction()?;
+        let note = insert_note(&tx, args, ctx.get_user_id())?;
+        tx.commit()?;
+        if note.remind_at.is_some() {
+            reminders::reschedule();
+        }
+        Ok(note)
     })
     .await
     .map_err(db::Error::from)
@@ -73,13 +608,42 @@ pub async fn create_note(CreateNote { title, text }: CreateNote, BaseParams { db
     .map_err(Error::from)
 }
 
-pub async fn get_note(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
+fn insert_note(
+    conn: &Connection,
+    CreateNote {
+        title,
+        text,
+        tags,
+        due_at,
+        remind_at,
+    }: CreateNote,
+    user_id: Option<Uuid>,
+) -> std::result::Result<Note, tokio_rusqlite::Error> {
+    let tags = normalize_tags(tags)?;
+    let mut note = conn.query_row(
+        r#"INSERT INTO notes (title, text, due_at, remind_at, created_by) VALUES (?, ?, ?, ?, ?)
+        RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
+        params![title, text, due_at, remind_at, user_id],
+        |row| Note::try_from(row),
+    )?;
+    replace_tags(conn, note.id, &tags)?;
+    update_links(conn, note.id)?;
+    load_details(conn, [&mut note])?;
+    Ok(note)
+}
+
+pub async fn get_note(note_id: Uuid, GetNote { html }: GetNote, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
     db.call(move |conn| {
-        let note = conn.query_row(
+        require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+        let mut note = conn.query_row(
             "SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes WHERE id = ?",
             params![note_id],
             |row| Note::try_from(row),
         )?;
+        load_details(conn, [&mut note])?;
+        if html {
+            load_html(conn, [&mut note])?;
+        }
         Ok(note)
     })
     .await
@@ -88,20 +652,495 @@ pub async fn get_note(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Resu
     .map_err(Error::from)
 }
 
+pub async fn render_note(note_id: Uuid, base: BaseParams) -> Result<RenderedNote> {
+    let Note { id, version, html, .. } = get_note(note_id, GetNote { html: true }, base).await?;
+    Ok(RenderedNote {
+        id,
+        version,
+        html: html.unwrap_or_default(),
+    })
+}
+
+/// Applies the patch to the note's current fields within one transaction.
 pub async fn update_note(
     note_id: Uuid,
-    UpdateNote { text, title }: UpdateNote,
+    patch: NotePatch,
+    if_match: Option<Precondition>,
     BaseParams { db, ctx }: BaseParams,
 ) -> Result<Note> {
     db.call(move |conn| {
-        conn.query_row(
-            r#"UPDATE notes SET text = coalesce(?, text), title = coalesce(?, title), updated_at = ?, updated_by = ?
-            WHERE id = ?
+        let tx = conn.transaction()?;
+        let note = patch_note(&tx, note_id, patch, if_match.as_ref(), ctx.get_user_id())?;
+        tx.commit()?;
+        if note.remind_at.is_some() {
+            reminders::reschedule();
+        }
+        Ok(note)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Note not found"))
+    .map_err(Error::from)
+}
+
+fn patch_note(
+    conn: &Connection,
+    note_id: Uuid,
+    patch: NotePatch,
+    if_match: Option<&Precondition>,
+    user_id: Option<Uuid>,
+) -> std::result::Result<Note, tokio_rusqlite::Error> {
+    require_access(conn, note_id, user_id, Access::Write)?;
+    require_version(conn, note_id, if_match)?;
+    let mut note = conn.query_row(
+        "SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes WHERE id = ?",
+        params![note_id],
+        |row| Note::try_from(row),
+    )?;
+    load_details(conn, [&mut note])?;
+
+    let NoteFields {
+        title,
+        text,
+        tags,
+        due_at,
+        remind_at,
+    } = apply_patch(
+        patch,
+        NoteFields {
+            title: note.title,
+            text: note.text,
+            tags: note.tags,
+            due_at: note.due_at,
+            remind_at: note.remind_at,
+        },
+    )?;
+    let tags = normalize_tags(tags)?;
+    let mut note = conn.query_row(
+        r#"UPDATE notes SET text = ?, title = ?, due_at = ?, remind_at = ?, updated_at = ?, updated_by = ?
+        WHERE id = ?
+        RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
+        params![text, title, due_at, remind_at, chrono::Utc::now(), user_id, note_id],
+        |row| Note::try_from(row),
+    )?;
+    set_tags(conn, note_id, &tags)?;
+    update_links(conn, note_id)?;
+    load_details(conn, [&mut note])?;
+    Ok(note)
+}
+
+/// The fields of a note a `PATCH` changes, JSON Patch paths point into this document.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoteFields {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    due_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    remind_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+fn apply_patch(patch: NotePatch, mut fields: NoteFields) -> Result<NoteFields> {
+    match patch {
+        NotePatch::Update(UpdateNote {
+            text,
+            title,
+            tags,
+            due_at,
+            remind_at,
+        }) => {
+            fields.title = title.unwrap_or(fields.title);
+            fields.text = text.unwrap_or(fields.text);
+            fields.tags = tags.unwrap_or(fields.tags);
+            fields.due_at = due_at.unwrap_or(fields.due_at);
+            fields.remind_at = remind_at.unwrap_or(fields.remind_at);
+        }
+        NotePatch::Merge(MergePatchNote {
+            text,
+            title,
+            tags,
+            due_at,
+            remind_at,
+        }) => {
+            if let Some(title) = title {
+                fields.title = title.unwrap_or_default();
+            }
+            if let Some(text) = text {
+                fields.text = text.unwrap_or_default();
+            }
+            if let Some(tags) = tags {
+                fields.tags = tags.unwrap_or_default();
+            }
+            if let Some(due_at) = due_at {
+                fields.due_at = due_at;
+            }
+            if let Some(remind_at) = remind_at {
+                fields.remind_at = remind_at;
+            }
+        }
+        NotePatch::Operations(operations) => {
+            let mut document = serde_json::to_value(fields).map_err(|e| Error::Unexpected(e.to_string()))?;
+            json_patch::patch(&mut document, &operations).map_err(|e| match e.kind {
+                json_patch::PatchErrorKind::TestFailed => Error::Conflict(e.to_string()),
+                _ => Error::Validation(e.to_string()),
+            })?;
+            fields = serde_json::from_value(document).map_err(|e| Error::Validation(format!("Invalid note: {e}")))?;
+        }
+    }
+    Ok(fields)
+}
+
+/// Moves the note to the trash, see [`purge_trash`].
+pub async fn delete_note(
+    note_id: Uuid,
+    if_match: Option<Precondition>,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Note> {
+    db.call(move |conn| trash_note(conn, note_id, if_match.as_ref(), ctx.get_user_id()))
+        .await
+        .map_err(db::Error::from)
+        .map_err(|e| db::Error::not_found_message(e, "Note not found"))
+        .map_err(Error::from)
+}
+
+fn trash_note(
+    conn: &Connection,
+    note_id: Uuid,
+    if_match: Option<&Precondition>,
+    user_id: Option<Uuid>,
+) -> std::result::Result<Note, tokio_rusqlite::Error> {
+    require_access(conn, note_id, user_id, Access::Owner)?;
+    require_version(conn, note_id, if_match)?;
+    let mut note = conn.query_row(
+        r#"UPDATE notes SET deleted_at = ?, deleted_by = ?
+        WHERE id = ?
+        RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
+        params![chrono::Utc::now(), user_id, note_id],
+        |row| Note::try_from(row),
+    )?;
+    load_details(conn, [&mut note])?;
+    Ok(note)
+}
+
+/// Runs the operations in one transaction, each within a savepoint so that a failed one leaves no trace.
+pub async fn batch_notes(
+    BatchNotes {
+        operations,
+        best_effort,
+    }: BatchNotes,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<BatchNotesResponse> {
+    if operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(Error::Validation(format!(
+            "A batch holds at most {MAX_BATCH_OPERATIONS} operations"
+        )));
+    }
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let mut tx = conn.transaction()?;
+        let mut results = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let savepoint = tx.savepoint()?;
+            let result = match operation {
+                BatchOperation::Create(args) => insert_note(&savepoint, args, user_id).map(|note| (201, note)),
+                BatchOperation::Update { id, version, note } => {
+                    let if_match = version.map(|version| Precondition::Tags(vec![EntityTag::new(version)]));
+                    patch_note(&savepoint, id, NotePatch::Update(note), if_match.as_ref(), user_id)
+                        .map(|note| (200, note))
+                }
+                BatchOperation::Delete { id, version } => {
+                    let if_match = version.map(|version| Precondition::Tags(vec![EntityTag::new(version)]));
+                    trash_note(&savepoint, id, if_match.as_ref(), user_id).map(|note| (200, note))
+                }
+            };
+            results.push(match result {
+                Ok((status, note)) => {
+                    savepoint.commit()?;
+                    BatchResult {
+                        status,
+                        note: Some(note),
+                        error: None,
+                    }
+                }
+                Err(error) => {
+                    let error = Error::from(db::Error::from(error).not_found_message("Note not found"));
+                    let error = ErrorResponse::from(&error);
+                    BatchResult {
+                        status: error.status,
+                        note: None,
+                        error: Some(error),
+                    }
+                }
+            });
+        }
+
+        let committed = best_effort || results.iter().all(|result| result.error.is_none());
+        if committed {
+            tx.commit()?;
+            reminders::reschedule();
+        }
+        Ok(BatchNotesResponse { committed, results })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// The caller's trashed notes. Shared notes are trashed by their owner, so they only show up in the owner's trash.
+pub async fn find_trash(retention_days: i64, BaseParams { db, ctx }: BaseParams) -> Result<FindTrashResponse> {
+    db.call(move |conn| {
+        let mut results = conn
+            .prepare(
+                r#"SELECT id, title, text, created_at, created_by, updated_at, updated_by, deleted_at, deleted_by
+                FROM notes
+                WHERE created_by = ? AND deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC"#,
+            )?
+            .query_map(params![ctx.get_user_id()], |row| {
+                let deleted_at: chrono::DateTime<chrono::Utc> = row.get(7)?;
+                Ok(TrashedNote {
+                    note: Note::try_from(row)?,
+                    deleted_at,
+                    deleted_by: row.get(8)?,
+                    purge_at: deleted_at + chrono::Duration::days(retention_days),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        load_details(conn, results.iter_mut().map(|trashed| &mut trashed.note))?;
+
+        Ok(FindTrashResponse { results })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn restore_note(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
+    db.call(move |conn| {
+        let mut note = conn.query_row(
+            r#"UPDATE notes SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = ? AND created_by = ? AND deleted_at IS NOT NULL
             RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
-            params![text, title, chrono::Utc::now(), ctx.clone().get_user_id(), note_id,],
+            params![note_id, ctx.get_user_id()],
             |row| Note::try_from(row),
+        )?;
+        load_details(conn, [&mut note])?;
+        if note.remind_at.is_some() {
+            reminders::reschedule();
+        }
+        Ok(note)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Note not found in trash"))
+    .map_err(Error::from)
+}
+
+/// Deletes a trashed note for good, along with its revisions and shares.
+pub async fn purge_note(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let mut note = tx.query_row(
+            r#"SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes
+            WHERE id = ? AND created_by = ? AND deleted_at IS NOT NULL"#,
+            params![note_id, ctx.get_user_id()],
+            |row| Note::try_from(row),
+        )?;
+        load_details(&tx, [&mut note])?;
+        tx.execute("DELETE FROM notes WHERE id = ?", params![note_id])?;
+        tx.commit()?;
+        Ok(note)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Note not found in trash"))
+    .map_err(Error::from)
+}
+
+/// Purges notes trashed more than `retention_days` ago, returns how many.
+pub async fn purge_trash(db: &DB, retention_days: i64) -> Result<usize> {
+    let deleted_before = chrono::Utc::now() - chrono::Duration::days(retention_days);
+    db.call(move |conn| {
+        conn.execute("DELETE FROM notes WHERE deleted_at < ?", params![deleted_before])
+            .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Validates tag names, dropping duplicates that only differ in case.
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut names = Vec::new();
+    for tag in tags {
+        let name = normalize_tag(&tag)?;
+        if seen.insert(name.to_lowercase()) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Replaces the tags of a note and bumps its version when they changed, see [`replace_tags`].
+pub(crate) fn set_tags(conn: &Connection, note_id: Uuid, names: &[String]) -> rusqlite::Result<()> {
+    let tag_ids = |conn: &Connection| {
+        conn.prepare_cached("SELECT tag_id FROM note_tags WHERE note_id = ? ORDER BY tag_id")?
+            .query_map(params![note_id], |row| row.get::<_, Uuid>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+    };
+    let before = tag_ids(conn)?;
+    replace_tags(conn, note_id, names)?;
+    if tag_ids(conn)? != before {
+        conn.execute("UPDATE notes SET version = version + 1 WHERE id = ?", params![note_id])?;
+    }
+    Ok(())
+}
+
+/// Replaces the tags of a note. Tags are owned by the owner of the note, missing ones are created.
+/// Leaves the version alone, for notes that were just inserted.
+pub(crate) fn replace_tags(conn: &Connection, note_id: Uuid, names: &[String]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM note_tags WHERE note_id = ?", params![note_id])?;
+    for name in names {
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (user_id, name) SELECT created_by, ?2 FROM notes WHERE id = ?1",
+            params![note_id, name],
+        )?;
+        conn.execute(
+            r#"INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+            SELECT notes.id, tags.id FROM notes JOIN tags ON tags.user_id = notes.created_by AND tags.name = ?2
+            WHERE notes.id = ?1"#,
+            params![note_id, name],
+        )?;
+    }
+    Ok(())
+}
+
+/// Fills in the versions, due dates, reminders, tags and breadcrumbs of `notes`.
+pub(crate) fn load_details<'a>(
+    conn: &Connection,
+    notes: impl IntoIterator<Item = &'a mut Note>,
+) -> rusqlite::Result<()> {
+    let mut fields = conn.prepare_cached("SELECT version, due_at, remind_at FROM notes WHERE id = ?")?;
+    let mut tags = conn.prepare_cached(
+        r#"SELECT tags.name FROM note_tags JOIN tags ON tags.id = note_tags.tag_id
+        WHERE note_tags.note_id = ?
+        ORDER BY tags.name"#,
+    )?;
+    let mut breadcrumbs = conn.prepare_cached(
+        r#"WITH RECURSIVE path (id, name, parent_id, depth) AS (
+            SELECT notebooks.id, notebooks.name, notebooks.parent_id, 0
+            FROM notes JOIN notebooks ON notebooks.id = notes.notebook_id
+            WHERE notes.id = ?
+            UNION ALL
+            SELECT notebooks.id, notebooks.name, notebooks.parent_id, path.depth + 1
+            FROM notebooks JOIN path ON notebooks.id = path.parent_id
         )
-        .map_err(|e| e.into())
+        SELECT id, name FROM path ORDER BY depth DESC"#,
+    )?;
+
+    for note in notes {
+        (note.version, note.due_at, note.remind_at) =
+            fields.query_row(params![note.id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        note.tags = tags
+            .query_map(params![note.id], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+        note.breadcrumbs = breadcrumbs
+            .query_map(params![note.id], |row| {
+                Ok(Breadcrumb {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                })
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        note.notebook_id = note.breadcrumbs.last().map(|breadcrumb| breadcrumb.id);
+    }
+    Ok(())
+}
+
+/// Fills in the `html` of `notes`, rendering the ones changed since they were cached. Needs the versions loaded.
+pub(crate) fn load_html<'a>(conn: &Connection, notes: impl IntoIterator<Item = &'a mut Note>) -> rusqlite::Result<()> {
+    let mut cached = conn.prepare_cached("SELECT html FROM note_html WHERE note_id = ? AND version = ?")?;
+    let mut cache = conn.prepare_cached(
+        r#"INSERT INTO note_html (note_id, version, html) VALUES (?1, ?2, ?3)
+        ON CONFLICT (note_id) DO UPDATE SET version = ?2, html = ?3"#,
+    )?;
+
+    for note in notes {
+        let html = match cached
+            .query_row(params![note.id, note.version], |row| row.get(0))
+            .optional()?
+        {
+            Some(html) => html,
+            None => {
+                let html = render_markdown(&note.text);
+                cache.execute(params![note.id, note.version, html])?;
+                html
+            }
+        };
+        note.html = Some(html);
+    }
+    Ok(())
+}
+
+lazy_static! {
+    static ref SANITIZER: ammonia::Builder<'static> = {
+        let mut sanitizer = ammonia::Builder::default();
+        sanitizer
+            .add_tags(["input"])
+            .add_tag_attributes("input", ["type", "checked", "disabled"])
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                // task list items only
+                ("input", "type") if value != "checkbox" => None,
+                _ => Some(value.into()),
+            });
+        sanitizer
+    };
+}
+
+/// CommonMark with tables, task lists and strikethrough, sanitized against XSS.
+fn render_markdown(text: &str) -> String {
+    let options = pulldown_cmark::Options::ENABLE_TABLES
+        | pulldown_cmark::Options::ENABLE_TASKLISTS
+        | pulldown_cmark::Options::ENABLE_STRIKETHROUGH;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new_ext(text, options));
+    SANITIZER.clean(&html).to_string()
+}
+
+/// Moves a note to one of its owner's notebooks.
+pub async fn move_note(
+    note_id: Uuid,
+    MoveNote { notebook_id }: MoveNote,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Note> {
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        require_access(conn, note_id, user_id, Access::Owner)?;
+        if let Some(notebook_id) = notebook_id {
+            conn.query_row(
+                "SELECT 1 FROM notebooks WHERE id = ? AND user_id = ?",
+                params![notebook_id, user_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .ok_or_else(|| Error::NotFound("Notebook not found".into()))?;
+        }
+
+        let mut note = conn.query_row(
+            r#"UPDATE notes SET notebook_id = ?
+            WHERE id = ?
+            RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
+            params![notebook_id, note_id],
+            |row| Note::try_from(row),
+        )?;
+        load_details(conn, [&mut note])?;
+        Ok(note)
     })
     .await
     .map_err(db::Error::from)
@@ -109,20 +1148,234 @@ pub async fn update_note(
     .map_err(Error::from)
 }
 
-pub async fn delete_note(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
+/// Revisions are written by triggers on `notes`, see the migrations.
+pub async fn find_revisions(note_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<FindRevisionsResponse> {
     db.call(move |conn| {
-        conn.query_row(
-            r#"DELETE FROM notes
+        require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+        let results = conn
+            .prepare(
+                r#"SELECT note_id, revision, title, text, updated_at, updated_by FROM note_revisions
+                WHERE note_id = ?
+                ORDER BY revision DESC"#,
+            )?
+            .query_map(params![note_id], |row| NoteRevision::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(FindRevisionsResponse { results })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+fn revision(conn: &Connection, note_id: Uuid, revision: u32) -> rusqlite::Result<NoteRevision> {
+    conn.query_row(
+        r#"SELECT note_id, revision, title, text, updated_at, updated_by FROM note_revisions
+        WHERE note_id = ? AND revision = ?"#,
+        params![note_id, revision],
+        |row| NoteRevision::try_from(row),
+    )
+}
+
+pub async fn get_revision(note_id: Uuid, number: u32, BaseParams { db, ctx }: BaseParams) -> Result<NoteRevision> {
+    db.call(move |conn| {
+        require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+        revision(conn, note_id, number).map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Revision not found"))
+    .map_err(Error::from)
+}
+
+/// Line-based diff of the title and text between two revisions.
+pub async fn diff_revisions(
+    note_id: Uuid,
+    DiffRevisions { from, to }: DiffRevisions,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<NoteDiff> {
+    let (old, new) = db
+        .call(move |conn| {
+            require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+            Ok((revision(conn, note_id, from)?, revision(conn, note_id, to)?))
+        })
+        .await
+        .map_err(db::Error::from)
+        .map_err(|e| db::Error::not_found_message(e, "Revision not found"))?;
+
+    Ok(NoteDiff {
+        from,
+        to,
+        title: diff_lines(&old.title, &new.title),
+        text: diff_lines(&old.text, &new.text),
+    })
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+            value: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect()
+}
+
+/// Sets the note back to an older revision, which is recorded as a new revision.
+pub async fn restore_revision(
+    note_id: Uuid,
+    number: u32,
+    if_match: Option<Precondition>,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Note> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        require_access(&tx, note_id, ctx.get_user_id(), Access::Write)?;
+        require_version(&tx, note_id, if_match.as_ref())?;
+        let NoteRevision { title, text, .. } = revision(&tx, note_id, number)?;
+        let mut note = tx.query_row(
+            r#"UPDATE notes SET title = ?, text = ?, updated_at = ?, updated_by = ?
             WHERE id = ?
             RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
-            params![note_id],
+            params![title, text, chrono::Utc::now(), ctx.get_user_id(), note_id],
             |row| Note::try_from(row),
+        )?;
+        update_links(&tx, note_id)?;
+        load_details(&tx, [&mut note])?;
+        tx.commit()?;
+        Ok(note)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Revision not found"))
+    .map_err(Error::from)
+}
+
+/// Notes other users shared with the caller.
+pub async fn find_shared_notes(BaseParams { db, ctx }: BaseParams) -> Result<FindSharedNotesResponse> {
+    db.call(move |conn| {
+        let mut notes = conn
+            .prepare(
+                r#"SELECT notes.id, title, text, notes.created_at, notes.created_by, updated_at, updated_by,
+                note_shares.permission
+                FROM notes JOIN note_shares ON note_shares.note_id = notes.id
+                WHERE note_shares.user_id = ? AND notes.deleted_at IS NULL
+                ORDER BY notes.id"#,
+            )?
+            .query_map(params![ctx.get_user_id()], |row| {
+                Ok(SharedNote {
+                    note: Note::try_from(row)?,
+                    permission: row.get(7)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        load_details(conn, notes.iter_mut().map(|shared| &mut shared.note))?;
+        Ok(FindSharedNotesResponse { results: notes })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Users a note is shared with, visible to everyone who can read the note.
+pub async fn find_collaborators(
+    note_id: Uuid,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<FindCollaboratorsResponse> {
+    db.call(move |conn| {
+        require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
+        let collaborators = conn
+            .prepare(
+                r#"SELECT users.id, users.email, note_shares.permission, note_shares.created_at
+                FROM note_shares JOIN users ON users.id = note_shares.user_id
+                WHERE note_shares.note_id = ?
+                ORDER BY note_shares.created_at"#,
+            )?
+            .query_map(params![note_id], |row| Collaborator::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindCollaboratorsResponse { results: collaborators })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Grants a user access to a note, or changes the permission they already have. Owner only.
+pub async fn share_note(
+    note_id: Uuid,
+    ShareNote { email, permission }: ShareNote,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Collaborator> {
+    let email = normalize_email(&email)?;
+    let owner_id = ctx.get_user_id();
+
+    db.call(move |conn| {
+        require_access(conn, note_id, owner_id, Access::Owner)?;
+
+        let user_id = conn
+            .query_row("SELECT id FROM users WHERE email = ?", params![email], |row| {
+                row.get::<_, Uuid>(0)
+            })
+            .optional()?
+            .ok_or_else(|| Error::NotFound("User not found".into()))?;
+        if Some(user_id) == owner_id {
+            return Err(Error::Validation("You can't share a note with yourself".into()).into());
+        }
+
+        conn.execute(
+            r#"INSERT INTO note_shares (note_id, user_id, permission, created_by) VALUES (?, ?, ?, ?)
+            ON CONFLICT (note_id, user_id) DO UPDATE SET permission = excluded.permission"#,
+            params![note_id, user_id, permission, owner_id],
+        )?;
+        relink_shared_note(conn, note_id)?;
+        conn.query_row(
+            r#"SELECT users.id, users.email, note_shares.permission, note_shares.created_at
+            FROM note_shares JOIN users ON users.id = note_shares.user_id
+            WHERE note_shares.note_id = ? AND note_shares.user_id = ?"#,
+            params![note_id, user_id],
+            |row| Collaborator::try_from(row),
         )
         .map_err(|e| e.into())
     })
     .await
     .map_err(db::Error::from)
-    .map_err(|e| db::Error::not_found_message(e, "Note not found"))
+    .map_err(Error::from)
+}
+
+/// Revokes a user's access to a note. Allowed to the owner, and to collaborators leaving the note.
+pub async fn unshare_note(note_id: Uuid, user_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Collaborator> {
+    let caller_id = ctx.get_user_id();
+    let required = if caller_id == Some(user_id) {
+        Access::Read
+    } else {
+        Access::Owner
+    };
+
+    db.call(move |conn| {
+        require_access(conn, note_id, caller_id, required)?;
+        let collaborator = conn.query_row(
+            r#"SELECT users.id, users.email, note_shares.permission, note_shares.created_at
+            FROM note_shares JOIN users ON users.id = note_shares.user_id
+            WHERE note_shares.note_id = ? AND note_shares.user_id = ?"#,
+            params![note_id, user_id],
+            |row| Collaborator::try_from(row),
+        )?;
+        conn.execute(
+            "DELETE FROM note_shares WHERE note_id = ? AND user_id = ?",
+            params![note_id, user_id],
+        )?;
+        relink_shared_note(conn, note_id)?;
+        Ok(collaborator)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Collaborator not found"))
     .map_err(Error::from)
 }
 
@@ -132,6 +1385,7 @@ pub mod views {
     pub async fn get_or_create_note(note_id: Option<Uuid>, BaseParams { db, ctx }: BaseParams) -> Result<Note> {
         db.call(move |conn| {
             let note = if let Some(note_id) = note_id {
+                require_access(conn, note_id, ctx.get_user_id(), Access::Read)?;
                 conn.query_row(
                     "SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes WHERE id = ?",
                     params![note_id],
@@ -158,11 +1412,12 @@ pub mod views {
         BaseParams { db, ctx }: BaseParams,
     ) -> Result<Note> {
         db.call(move |conn| {
+            require_access(conn, note_id, ctx.get_user_id(), Access::Write)?;
             conn.query_row(
                 r#"UPDATE notes SET text = ?, title = ?, updated_at = ?, updated_by = ?
                 WHERE id = ?
                 RETURNING id, title, text, created_at, created_by, updated_at, updated_by"#,
-                params![text, title, chrono::Utc::now(), ctx.clone().get_user_id(), note_id],
+                params![text, title, chrono::Utc::now(), ctx.get_user_id(), note_id],
                 |row| Note::try_from(row),
             )
             .map_err(|e| e.into())
@@ -186,10 +1441,29 @@ mod tests {
     #[tokio::test]
     async fn test_find_notes() -> Result<()> {
         let db = init_test_db().await?;
-        let notes = find_notes(BaseParams {
-            ctx: Ctx::new(None),
-            db,
-        });
+        let notes = find_notes(
+            FindNotes::default(),
+            BaseParams {
+                ctx: Ctx::new(None),
+                db,
+            },
+        );
         Ok(())
     }
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(match_query("rust  sqlite").as_deref(), Some(r#""rust" "sqlite""#));
+        assert_eq!(
+            match_query("sql* \"full text\"").as_deref(),
+            Some(r#""sql"* "full text""#)
+        );
+        assert_eq!(match_query("\"full te\"*").as_deref(), Some(r#""full te"*"#));
+        // operators and column filters are searched for
+        assert_eq!(
+            match_query("title:x OR y\"").as_deref(),
+            Some(r#""title:x" "OR" "y""""#)
+        );
+        assert_eq!(match_query(" * \"\" "), None);
+    }
 }
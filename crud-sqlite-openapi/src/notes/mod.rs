@@ -2,10 +2,29 @@ mod handlers;
 mod model;
 mod routes;
 
+use std::time::Duration;
+
+pub(crate) use handlers::{load_details, normalize_tags, replace_tags, require_access, set_tags, Access};
+pub use model::Note;
 use model::*;
 
-use crate::{openapi::aide::axum::ApiRouter, state::AppState};
+use crate::{openapi::aide::axum::ApiRouter, state::AppState, DB};
 
 pub fn router(state: AppState) -> ApiRouter {
     ApiRouter::new().merge(routes::router(state.clone()))
 }
+
+/// Purges expired notes from the trash every hour, starting right away.
+pub fn spawn_trash_purge(db: DB, retention_days: i64) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match handlers::purge_trash(&db, retention_days).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged trashed notes"),
+                Err(error) => tracing::error!(?error, "failed to purge trashed notes"),
+            }
+        }
+    })
+}
@@ -1,7 +1,10 @@
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
+use crate::errors::{ErrorResponse, ErrorResponseDocs};
+
 pub type UserId = Uuid;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -13,6 +16,35 @@ pub struct Note {
     pub created_by: Option<UserId>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_by: Option<UserId>,
+    /// Incremented on every change, sent as the `ETag`.
+    #[serde(default)]
+    pub version: u32,
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the owner is notified about the note.
+    pub remind_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Tag names, in alphabetical order.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Notebook the note is in, unset at the top level.
+    pub notebook_id: Option<Uuid>,
+    /// Notebooks from the top level down to `notebook_id`.
+    #[serde(default)]
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// `text` rendered from Markdown, when requested with `html`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Breadcrumb {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveNote {
+    /// Target notebook, the top level when unset.
+    pub notebook_id: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -26,15 +58,349 @@ pub struct UpdateNoteForm {
 pub struct UpdateNote {
     pub text: Option<String>,
     pub title: Option<String>,
+    /// Replaces all tags of the note.
+    pub tags: Option<Vec<String>>,
+    /// `null` clears it.
+    #[serde(default, deserialize_with = "nullable")]
+    pub due_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    /// `null` clears it. A new time fires again.
+    #[serde(default, deserialize_with = "nullable")]
+    pub remind_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+}
+
+/// Body of `PATCH /notes/{id}`, by content type.
+#[derive(Debug)]
+pub enum NotePatch {
+    /// `application/json`, fields that are set replace the note's.
+    Update(UpdateNote),
+    /// `application/merge-patch+json`.
+    Merge(MergePatchNote),
+    /// `application/json-patch+json`.
+    Operations(json_patch::Patch),
+}
+
+/// RFC 7396 merge patch, missing fields stay unchanged and `null` clears a field. Other members are ignored.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MergePatchNote {
+    #[serde(default, deserialize_with = "nullable")]
+    pub text: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub title: Option<Option<String>>,
+    /// Replaces all tags of the note.
+    #[serde(default, deserialize_with = "nullable")]
+    pub tags: Option<Option<Vec<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub due_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub remind_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+}
+
+/// Tells a `null` field (`Some(None)`) from a missing one (`None`).
+fn nullable<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// RFC 6902 operation on the note as `{"title": "", "text": "", "tags": [], "due_at": null, "remind_at": null}`,
+/// only documents the schema.
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add {
+        path: String,
+        value: Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    /// Fails the whole patch with 409 unless the value at `path` equals `value`.
+    Test {
+        path: String,
+        value: Value,
+    },
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateNote {
     pub text: String,
     pub title: String,
+    /// Tag names. Tags the owner doesn't have yet are created.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the owner is notified about the note.
+    pub remind_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSort {
+    #[default]
+    CreatedAt,
+    /// Last update, or creation for notes never updated.
+    UpdatedAt,
+    Title,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// Notes with every one of the tags.
+    #[default]
+    All,
+    /// Notes with at least one of the tags.
+    Any,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters the notes the caller can read, their own and shared ones.
+/// Newest first by default.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct FindNotes {
+    /// Substring of the title, ignoring the case of ASCII letters only.
+    pub title: Option<String>,
+    pub created_by: Option<UserId>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Comma separated tag names.
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub tag_match: TagMatch,
+    #[serde(default)]
+    pub sort: NoteSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Page size, 50 by default and at most 200.
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page, only valid with the same filters and sort.
+    pub cursor: Option<String>,
+    /// Count all matching notes in `total`.
+    #[serde(default)]
+    pub with_total: bool,
+    /// Render the text of each note into `html`.
+    #[serde(default)]
+    pub html: bool,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct GetNote {
+    /// Render the text into `html`.
+    #[serde(default)]
+    pub html: bool,
+}
+
+/// Sanitized HTML of a note's Markdown text.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RenderedNote {
+    pub id: Uuid,
+    /// Version of the note the HTML was rendered from.
+    pub version: u32,
+    pub html: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct FindNotesResponse {
     pub results: Vec<Note>,
+    /// Cursor of the next page, `None` on the last one.
+    pub next_cursor: Option<String>,
+    /// Set when requested with `with_total`.
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    Read,
+    /// Read and update, but not delete or share.
+    Write,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ShareNote {
+    /// Email of the user to share with.
+    pub email: String,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Collaborator {
+    pub user_id: UserId,
+    pub email: String,
+    pub permission: SharePermission,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindCollaboratorsResponse {
+    pub results: Vec<Collaborator>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SharedNote {
+    #[serde(flatten)]
+    pub note: Note,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindSharedNotesResponse {
+    pub results: Vec<SharedNote>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchNotes {
+    /// Words to match in the title or text. `"quoted words"` match a phrase, a trailing `*` a prefix.
+    pub q: String,
+    /// Number of results, 20 by default and at most 200.
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NoteSearchResult {
+    #[serde(flatten)]
+    pub note: Note,
+    /// HTML-escaped title with the matches wrapped in `<mark>` tags.
+    pub title_snippet: String,
+    /// Fragment of the text around the matches, highlighted like `title_snippet`.
+    pub text_snippet: String,
+    /// BM25 score, lower is a better match.
+    pub rank: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SearchNotesResponse {
+    /// Best matches first.
+    pub results: Vec<NoteSearchResult>,
+}
+
+/// Title and text of a note after one of its updates.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NoteRevision {
+    pub note_id: Uuid,
+    /// Starts at 1 for the note as created.
+    pub revision: u32,
+    pub title: String,
+    pub text: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub updated_by: Option<UserId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindRevisionsResponse {
+    /// Newest first.
+    pub results: Vec<NoteRevision>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DiffRevisions {
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    /// 1-based line number in `from`, unset for inserted lines.
+    pub old_line: Option<usize>,
+    /// 1-based line number in `to`, unset for deleted lines.
+    pub new_line: Option<usize>,
+    /// The line without its line break.
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NoteDiff {
+    pub from: u32,
+    pub to: u32,
+    pub title: Vec<DiffLine>,
+    pub text: Vec<DiffLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TrashedNote {
+    #[serde(flatten)]
+    pub note: Note,
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+    pub deleted_by: Option<UserId>,
+    /// When the note is purged for good unless restored.
+    pub purge_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindTrashResponse {
+    /// Most recently deleted first.
+    pub results: Vec<TrashedNote>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchNotes {
+    pub operations: Vec<BatchOperation>,
+    /// Commits the operations that succeed. By default nothing is committed when any operation fails.
+    #[serde(default)]
+    pub best_effort: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Create(CreateNote),
+    Update {
+        id: Uuid,
+        /// Fails the operation with 412 when the note is at another version.
+        version: Option<u32>,
+        #[serde(flatten)]
+        note: UpdateNote,
+    },
+    /// Moves the note to the trash.
+    Delete {
+        id: Uuid,
+        /// Fails the operation with 412 when the note is at another version.
+        version: Option<u32>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BatchNotesResponse {
+    /// Unset when a failed operation rolled back the whole batch.
+    pub committed: bool,
+    /// One result per operation, in order.
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BatchResult {
+    /// Status the operation would have as a request of its own.
+    pub status: u16,
+    pub note: Option<Note>,
+    #[schemars(with = "Option<ErrorResponseDocs>")]
+    pub error: Option<ErrorResponse>,
 }
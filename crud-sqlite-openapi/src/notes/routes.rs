@@ -1,22 +1,41 @@
+use std::sync::Arc;
+
 use crate::{
-    ctx::BaseParams,
+    config::Config,
+    etag::{IfMatch, IfNoneMatch, Versioned},
     openapi::{
-        aide::{
-            axum::{routing::get, ApiRouter, IntoApiResponse},
-            NoApi,
+        aide::axum::{
+            routing::{delete_with, get_with, post_with},
+            ApiRouter, IntoApiResponse,
         },
-        Json, Path,
+        Json, Path, Query, TransformOperationExt,
+    },
+    policy::{
+        policies::{NotesRead, NotesWrite},
+        Authorized,
     },
     state::AppState,
+    Error,
 };
-use axum::http::StatusCode;
-
+use aide::{
+    generate::GenContext,
+    openapi::{MediaType, Operation, RequestBody, SchemaObject},
+    operation::{set_body, OperationInput},
+};
+use axum::{
+    extract::{FromRequest, Request, State},
+    http::{header, StatusCode},
+};
+use indexmap::IndexMap;
 use schemars::JsonSchema;
 
 use serde::Deserialize;
 use uuid::Uuid;
 
-use super::{CreateNote, Note, UpdateNote};
+use super::{
+    BatchNotes, Collaborator, CreateNote, DiffRevisions, FindNotes, GetNote, MergePatchNote, MoveNote, Note, NotePatch,
+    PatchOperation, SearchNotes, ShareNote, UpdateNote,
+};
 
 use super::handlers;
 
@@ -25,49 +44,392 @@ struct NoteIdPath {
     note_id: Uuid,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RevisionPath {
+    note_id: Uuid,
+    revision: u32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CollaboratorPath {
+    note_id: Uuid,
+    user_id: Uuid,
+}
+
+impl<S: Send + Sync> FromRequest<S> for NotePatch {
+    type Rejection = Error;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase());
+
+        Ok(match content_type.as_deref() {
+            Some("application/merge-patch+json") => Self::Merge(Json::from_request(request, state).await?.0),
+            Some("application/json-patch+json") => Self::Operations(Json::from_request(request, state).await?.0),
+            _ => Self::Update(Json::from_request(request, state).await?.0),
+        })
+    }
+}
+
+impl OperationInput for NotePatch {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        let media_type = |schema: schemars::schema::Schema| MediaType {
+            schema: Some(SchemaObject {
+                json_schema: schema,
+                example: None,
+                external_docs: None,
+            }),
+            ..Default::default()
+        };
+        let content = IndexMap::from_iter([
+            (
+                "application/json".into(),
+                media_type(ctx.schema.subschema_for::<UpdateNote>()),
+            ),
+            (
+                "application/merge-patch+json".into(),
+                media_type(ctx.schema.subschema_for::<MergePatchNote>()),
+            ),
+            (
+                "application/json-patch+json".into(),
+                media_type(ctx.schema.subschema_for::<Vec<PatchOperation>>()),
+            ),
+        ]);
+        set_body(
+            ctx,
+            operation,
+            RequestBody {
+                content,
+                required: true,
+                ..Default::default()
+            },
+        );
+    }
+}
+
 pub fn router(state: AppState) -> ApiRouter {
     ApiRouter::new()
         .api_route(
             "/api/v1/notes",
-            get(find_notes).post_with(create_note, |t| t.response::<201, Json<Note>>()),
+            get_with(find_notes, |t| {
+                t.description(
+                    "Lists the caller's notes and the ones shared with them, newest first, one page at a time.",
+                )
+                .error::<400>("Invalid limit or cursor")
+            })
+            .post_with(create_note, |t| t.response::<201, Json<Note>>()),
+        )
+        .api_route(
+            "/api/v1/notes:batch",
+            post_with(batch_notes, |t| {
+                t.description(
+                    "Creates, updates and deletes notes in one transaction. \
+                    Failed operations are reported in their result, with the error they would have on their own.",
+                )
+                .error::<400>("Too many operations")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/search",
+            get_with(search_notes, |t| {
+                t.description("Full-text search over the title and text of the notes the caller can read.")
+                    .error::<400>("Empty query or invalid limit")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/trash",
+            get_with(find_trash, |t| {
+                t.description("The caller's deleted notes. They are purged for good after the retention period.")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/trash/{note_id}",
+            delete_with(purge_note, |t| {
+                t.description("Deletes a trashed note for good.")
+                    .error::<404>("Note not found in trash")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/trash/{note_id}/restore",
+            post_with(restore_note, |t| {
+                t.description("Takes the note out of the trash.")
+                    .error::<404>("Note not found in trash")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/shared",
+            get_with(find_shared_notes, |t| {
+                t.description("Notes other users shared with the caller.")
+            }),
         )
         .api_route(
             "/api/v1/notes/{note_id}",
-            get(get_note).patch(update_note).delete(delete_note),
+            get_with(get_note, |t| {
+                t.response_with::<304, (), _>(|r| r.description("The `If-None-Match` version is current"))
+                    .error::<404>("Note not found")
+            })
+            .patch_with(update_note, |t| {
+                t.description(
+                    "Updates the note. Send `application/merge-patch+json` (RFC 7396) to clear fields with `null`, \
+                    or `application/json-patch+json` (RFC 6902) to apply operations to \
+                    `{title, text, tags, due_at, remind_at}`.",
+                )
+                .error::<400>("Invalid patch, or the patched note is invalid")
+                .error::<403>("Read only access")
+                .error::<404>("Note not found")
+                .error::<409>("A `test` operation failed")
+                .error::<412>("Note changed since the `If-Match` version")
+            })
+            .delete_with(delete_note, |t| {
+                t.description("Moves the note to the trash. Only the owner can delete.")
+                    .error::<403>("Not the owner")
+                    .error::<404>("Note not found")
+                    .error::<412>("Note changed since the `If-Match` version")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/render",
+            get_with(render_note, |t| {
+                t.description(
+                    "The text rendered from Markdown (CommonMark with tables and task lists) to sanitized HTML.",
+                )
+                .response_with::<304, (), _>(|r| r.description("The `If-None-Match` version is current"))
+                .error::<404>("Note not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/move",
+            post_with(move_note, |t| {
+                t.description("Moves the note to one of the owner's notebooks, or to the top level.")
+                    .error::<403>("Not the owner")
+                    .error::<404>("Note or notebook not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/revisions",
+            get_with(find_revisions, |t| {
+                t.description("Revisions of the note, newest first. Every change of title or text is one.")
+                    .error::<404>("Note not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/revisions/{revision}",
+            get_with(get_revision, |t| t.error::<404>("Note or revision not found")),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/revisions/{revision}/restore",
+            post_with(restore_revision, |t| {
+                t.description("Sets the note back to the revision, recorded as a new revision.")
+                    .error::<403>("Read only access")
+                    .error::<404>("Note or revision not found")
+                    .error::<412>("Note changed since the `If-Match` version")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/diff",
+            get_with(diff_revisions, |t| {
+                t.description("Line-based diff of the title and text between two revisions.")
+                    .error::<404>("Note or revision not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/shares",
+            get_with(find_collaborators, |t| {
+                t.description("Users the note is shared with.")
+                    .error::<404>("Note not found")
+            })
+            .post_with(share_note, |t| {
+                t.description("Shares the note with a user, or changes their permission. Only the owner can share.")
+                    .response::<201, Json<Collaborator>>()
+                    .error::<400>("Sharing with yourself")
+                    .error::<403>("Not the owner")
+                    .error::<404>("Note or user not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/{note_id}/shares/{user_id}",
+            delete_with(unshare_note, |t| {
+                t.description("Revokes a user's access. The owner can remove anyone, collaborators only themselves.")
+                    .error::<403>("Not the owner")
+                    .error::<404>("Note or collaborator not found")
+            }),
         )
         .with_state(state)
 }
 
-async fn find_notes(NoApi(base): NoApi<BaseParams>) -> impl IntoApiResponse {
-    handlers::find_notes(base).await.map(Json)
+async fn find_notes(Query(args): Query<FindNotes>, Authorized(base, _): Authorized<NotesRead>) -> impl IntoApiResponse {
+    handlers::find_notes(args, base).await.map(Json)
 }
 
-async fn create_note(NoApi(base): NoApi<BaseParams>, Json(args): Json<CreateNote>) -> impl IntoApiResponse {
+async fn search_notes(
+    Query(args): Query<SearchNotes>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::search_notes(args, base).await.map(Json)
+}
+
+async fn create_note(
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<CreateNote>,
+) -> impl IntoApiResponse {
     handlers::create_note(args, base)
         .await
-        .map(|r| (StatusCode::CREATED, Json(r)))
+        .map(|r| (StatusCode::CREATED, Versioned::new(r.version, r)))
+}
+
+async fn batch_notes(
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<BatchNotes>,
+) -> impl IntoApiResponse {
+    handlers::batch_notes(args, base).await.map(Json)
 }
 
 async fn get_note(
     Path(NoteIdPath { note_id }): Path<NoteIdPath>,
-    NoApi(base): NoApi<BaseParams>,
+    Authorized(base, _): Authorized<NotesRead>,
+    if_none_match: IfNoneMatch,
+    Query(args): Query<GetNote>,
+) -> impl IntoApiResponse {
+    let html = args.html;
+    handlers::get_note(note_id, args, base).await.map(|r| {
+        let versioned = Versioned::new(r.version, r);
+        let versioned = if html {
+            versioned.representation("html")
+        } else {
+            versioned
+        };
+        versioned.unless_cached(&if_none_match)
+    })
+}
+
+async fn render_note(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+    if_none_match: IfNoneMatch,
 ) -> impl IntoApiResponse {
-    handlers::get_note(note_id, base).await.map(Json)
+    handlers::render_note(note_id, base).await.map(|r| {
+        Versioned::new(r.version, r)
+            .representation("rendered")
+            .unless_cached(&if_none_match)
+    })
 }
 
 async fn update_note(
     Path(NoteIdPath { note_id }): Path<NoteIdPath>,
-    NoApi(base): NoApi<BaseParams>,
-    Json(args): Json<UpdateNote>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    IfMatch(if_match): IfMatch,
+    patch: NotePatch,
 ) -> impl IntoApiResponse {
-    handlers::update_note(note_id, args, base).await.map(Json)
+    handlers::update_note(note_id, patch, if_match, base)
+        .await
+        .map(|r| Versioned::new(r.version, r))
 }
 
 async fn delete_note(
     Path(NoteIdPath { note_id }): Path<NoteIdPath>,
-    NoApi(base): NoApi<BaseParams>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    IfMatch(if_match): IfMatch,
+) -> impl IntoApiResponse {
+    handlers::delete_note(note_id, if_match, base).await.map(Json)
+}
+
+async fn move_note(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<MoveNote>,
+) -> impl IntoApiResponse {
+    handlers::move_note(note_id, args, base)
+        .await
+        .map(|r| Versioned::new(r.version, r))
+}
+
+async fn find_revisions(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_revisions(note_id, base).await.map(Json)
+}
+
+async fn get_revision(
+    Path(RevisionPath { note_id, revision }): Path<RevisionPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::get_revision(note_id, revision, base).await.map(Json)
+}
+
+async fn restore_revision(
+    Path(RevisionPath { note_id, revision }): Path<RevisionPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    IfMatch(if_match): IfMatch,
+) -> impl IntoApiResponse {
+    handlers::restore_revision(note_id, revision, if_match, base)
+        .await
+        .map(|r| Versioned::new(r.version, r))
+}
+
+async fn diff_revisions(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+    Query(args): Query<DiffRevisions>,
+) -> impl IntoApiResponse {
+    handlers::diff_revisions(note_id, args, base).await.map(Json)
+}
+
+async fn find_trash(
+    State(config): State<Arc<Config>>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_trash(config.trash_retention_days, base).await.map(Json)
+}
+
+async fn restore_note(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+) -> impl IntoApiResponse {
+    handlers::restore_note(note_id, base)
+        .await
+        .map(|r| Versioned::new(r.version, r))
+}
+
+async fn purge_note(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+) -> impl IntoApiResponse {
+    handlers::purge_note(note_id, base).await.map(Json)
+}
+
+async fn find_shared_notes(Authorized(base, _): Authorized<NotesRead>) -> impl IntoApiResponse {
+    handlers::find_shared_notes(base).await.map(Json)
+}
+
+async fn find_collaborators(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
 ) -> impl IntoApiResponse {
-    handlers::delete_note(note_id, base).await.map(Json)
+    handlers::find_collaborators(note_id, base).await.map(Json)
+}
+
+async fn share_note(
+    Path(NoteIdPath { note_id }): Path<NoteIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<ShareNote>,
+) -> impl IntoApiResponse {
+    handlers::share_note(note_id, args, base)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+async fn unshare_note(
+    Path(CollaboratorPath { note_id, user_id }): Path<CollaboratorPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+) -> impl IntoApiResponse {
+    handlers::unshare_note(note_id, user_id, base)
+        .await
+        .map(Json::<Collaborator>)
 }
 
 #[cfg(test)]
@@ -75,10 +437,18 @@ mod tests {
     use crate::{
         db::{init_test_db, DB},
         errors::Result,
-        notes::{FindNotesResponse, Note},
+        notes::{
+            BatchNotesResponse, DiffOp, FindCollaboratorsResponse, FindNotesResponse, FindRevisionsResponse,
+            FindSharedNotesResponse, FindTrashResponse, Note, NoteDiff, NoteRevision, SearchNotesResponse,
+            SharePermission,
+        },
+        tests::{authenticate, TEST_USER_ID},
     };
     use axum_test::TestServer;
     use serde_json::json;
+    use uuid::{uuid, Uuid};
+
+    const OTHER_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000002");
 
     #[tokio::test]
     async fn find_notes() -> Result<()> {
@@ -106,6 +476,285 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn paginate_notes() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            for i in 0..5 {
+                conn.execute(
+                    "INSERT INTO notes (title, text, created_by) VALUES (?, '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'))",
+                    [i.to_string()],
+                )
+                .unwrap();
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db).await?;
+
+        let page = server
+            .get("/api/v1/notes?limit=2&with_total=true")
+            .await
+            .json::<FindNotesResponse>();
+        assert_eq!(page.total, Some(5));
+        let mut titles: Vec<_> = page.results.into_iter().map(|n| n.title).collect();
+        let mut cursor = page.next_cursor;
+
+        while let Some(next) = cursor {
+            let page = server
+                .get(&format!("/api/v1/notes?limit=2&cursor={next}"))
+                .await
+                .json::<FindNotesResponse>();
+            assert!(page.total.is_none());
+            titles.extend(page.results.into_iter().map(|n| n.title));
+            cursor = page.next_cursor;
+        }
+        assert_eq!(titles, ["4", "3", "2", "1", "0"]);
+
+        let response = server.get("/api/v1/notes?cursor=nope").expect_failure().await;
+        assert_eq!(response.status_code(), 400);
+        let response = server.get("/api/v1/notes?limit=0").expect_failure().await;
+        assert_eq!(response.status_code(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filter_and_sort_notes() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (title, text, created_by) VALUES ('banana', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (title, text, created_by) VALUES ('apple', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (title, text, created_by) VALUES ('cherry pie', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (id, title, text, created_by, created_at) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'apricot', '', uuid_blob('018f6146-32f4-7948-8289-000000000002'), '2024-05-01 12:00:00');
+                INSERT INTO notes (title, text, created_by) VALUES ('avocado', '', uuid_blob('018f6146-32f4-7948-8289-000000000002'));
+                INSERT INTO note_shares (note_id, user_id, permission) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'read');
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db).await?;
+        let titles = |page: &FindNotesResponse| page.results.iter().map(|n| n.title.clone()).collect::<Vec<_>>();
+
+        let page = server
+            .get("/api/v1/notes?title=A&sort=title&order=asc&limit=2&with_total=true")
+            .await
+            .json::<FindNotesResponse>();
+        assert_eq!(titles(&page), ["apple", "apricot"]);
+        assert_eq!(page.total, Some(3));
+
+        let cursor = page.next_cursor.unwrap();
+        let page = server
+            .get(&format!(
+                "/api/v1/notes?title=A&sort=title&order=asc&limit=2&cursor={cursor}"
+            ))
+            .await
+            .json::<FindNotesResponse>();
+        assert_eq!(titles(&page), ["banana"]);
+        assert!(page.next_cursor.is_none());
+
+        // cursors are bound to their sort
+        let response = server
+            .get(&format!("/api/v1/notes?cursor={cursor}"))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 400);
+
+        let page = server
+            .get(&format!("/api/v1/notes?created_by={OTHER_USER_ID}"))
+            .await
+            .json::<FindNotesResponse>();
+        assert_eq!(titles(&page), ["apricot"]);
+
+        let page = server
+            .get("/api/v1/notes?created_after=2100-01-01T00:00:00Z")
+            .await
+            .json::<FindNotesResponse>();
+        assert!(page.results.is_empty());
+
+        // CURRENT_TIMESTAMP and RFC 3339 dates compare as dates
+        let page = server
+            .get("/api/v1/notes?created_after=2024-05-01T12:00:00Z&created_before=2024-05-01T14:00:01%2B02:00")
+            .await
+            .json::<FindNotesResponse>();
+        assert_eq!(titles(&page), ["apricot"]);
+
+        let page = server
+            .get("/api/v1/notes?title=_&updated_before=2100-01-01T00:00:00Z")
+            .await
+            .json::<FindNotesResponse>();
+        assert!(page.results.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sort_by_updated_at() -> Result<()> {
+        let db = init_test_db().await?;
+
+        // created_at as written by CURRENT_TIMESTAMP, updated_at as written by chrono
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO notes (title, text, created_by, created_at) VALUES ('10:00', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), '2024-05-01 10:00:00');
+                INSERT INTO notes (title, text, created_by, created_at, updated_at) VALUES ('11:00', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), '2024-04-01 00:00:00', '2024-05-01 11:00:00.250+00:00');
+                INSERT INTO notes (title, text, created_by, created_at, updated_at) VALUES ('12:00', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), '2024-04-01 00:00:00', '2024-05-01 14:00:00+02:00');
+                INSERT INTO notes (title, text, created_by, created_at) VALUES ('13:00', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), '2024-05-01 13:00:00');
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db).await?;
+        let mut titles = Vec::new();
+        let mut cursor = None;
+        loop {
+            let url = match &cursor {
+                Some(cursor) => format!("/api/v1/notes?sort=updated_at&order=asc&limit=1&cursor={cursor}"),
+                None => "/api/v1/notes?sort=updated_at&order=asc&limit=1".into(),
+            };
+            let page = server.get(&url).await.json::<FindNotesResponse>();
+            titles.extend(page.results.into_iter().map(|n| n.title));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(titles, ["10:00", "11:00", "12:00", "13:00"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_notes() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'Groceries', 'eggs, milk and bread', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (title, text, created_by) VALUES ('Bread recipe', 'flour, water, salt', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (title, text, created_by) VALUES ('Secret bread', 'not yours', uuid_blob('018f6146-32f4-7948-8289-000000000002'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db.clone()).await?;
+        let search = |q: &str| {
+            let request = server.get("/api/v1/notes/search").add_query_param("q", q);
+            async { request.await.json::<SearchNotesResponse>().results }
+        };
+
+        // title matches rank first, other users' notes are left out
+        let results = search("bread").await;
+        let titles: Vec<_> = results.iter().map(|r| r.note.title.as_str()).collect();
+        assert_eq!(titles, ["Bread recipe", "Groceries"]);
+        assert_eq!(results[0].title_snippet, "<mark>Bread</mark> recipe");
+        assert_eq!(results[1].text_snippet, "eggs, milk and <mark>bread</mark>");
+
+        assert_eq!(search("gro*").await.len(), 1);
+        assert_eq!(search("\"milk and bread\"").await.len(), 1);
+        assert!(search("\"bread and milk\"").await.is_empty());
+
+        // the index follows updates
+        server
+            .patch("/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4")
+            .json(&json!({ "text": "cheese" }))
+            .await;
+        assert!(search("milk").await.is_empty());
+        assert_eq!(search("cheese").await.len(), 1);
+
+        // the note's own markup is escaped
+        server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "<b>bread</b> & butter", "text": "" }))
+            .await;
+        let results = search("butter").await;
+        assert_eq!(
+            results[0].title_snippet,
+            "&lt;b&gt;bread&lt;/b&gt; &amp; <mark>butter</mark>"
+        );
+
+        // VACUUM may renumber the rowids of notes, the index stays attached to the right notes
+        db.call(|conn| Ok(conn.execute_batch("DELETE FROM notes WHERE title = 'Bread recipe'; VACUUM;")?))
+            .await
+            .unwrap();
+        let results = search("bread").await;
+        let titles: Vec<_> = results.iter().map(|r| r.note.title.as_str()).collect();
+        assert_eq!(titles, ["<b>bread</b> & butter"]);
+
+        let response = server
+            .get("/api/v1/notes/search")
+            .add_query_param("q", " ")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tag_notes() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db).await?;
+
+        let note = server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "bread", "text": "", "tags": ["recipes", " baking ", "Recipes"] }))
+            .await
+            .json::<Note>();
+        assert_eq!(note.tags, ["baking", "recipes"]);
+        server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "soup", "text": "", "tags": ["recipes"] }))
+            .await;
+        server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "untagged", "text": "" }))
+            .await;
+
+        let titles = |url: &str| {
+            let request = server.get(url);
+            async {
+                let notes = request.await.json::<FindNotesResponse>().results;
+                notes.into_iter().map(|n| n.title).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(titles("/api/v1/notes?tags=recipes,BAKING").await, ["bread"]);
+        assert_eq!(titles("/api/v1/notes?tags=recipes").await, ["soup", "bread"]);
+        assert_eq!(titles("/api/v1/notes?tags=baking,nope&tag_match=any").await, ["bread"]);
+
+        let url = format!("/api/v1/notes/{}", note.id);
+        let response = server.patch(&url).json(&json!({ "tags": ["bread"] })).await;
+        assert_eq!(response.json::<Note>().tags, ["bread"]);
+        // tags are kept unless given
+        let response = server.patch(&url).json(&json!({ "text": "flour" })).await;
+        assert_eq!(response.json::<Note>().tags, ["bread"]);
+
+        let response = server
+            .patch(&url)
+            .json(&json!({ "tags": ["a,b"] }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 400);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn create_note() -> Result<()> {
         let db = init_test_db().await?;
@@ -124,13 +773,65 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn batch_notes() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db.clone()).await?;
+        let operations = json!([
+            { "op": "create", "title": "second", "text": "2", "tags": ["import"] },
+            { "op": "update", "id": "018f6138-5b4f-722d-97c5-29b927cedbd4", "version": 1, "text": "one" },
+            { "op": "update", "id": "018f6138-5b4f-722d-97c5-29b927cedbd4", "version": 1, "text": "uno" },
+            { "op": "delete", "id": "018f6138-5b4f-722d-97c5-000000000000" },
+        ]);
+        let count_notes =
+            || db.call(|conn| Ok(conn.query_row::<u32, _, _>("SELECT count(*) FROM notes", [], |r| r.get(0))?));
+
+        let response = server
+            .post("/api/v1/notes:batch")
+            .json(&json!({ "operations": operations }))
+            .await
+            .json::<BatchNotesResponse>();
+        assert!(!response.committed);
+        let statuses = response.results.iter().map(|r| r.status).collect::<Vec<_>>();
+        assert_eq!(statuses, [201, 200, 412, 404]);
+        let error = response.results[2].error.as_ref().unwrap();
+        assert_eq!(error.error, "precondition_failed");
+        assert_eq!(count_notes().await.unwrap(), 1);
+
+        let response = server
+            .post("/api/v1/notes:batch")
+            .json(&json!({ "operations": operations, "best_effort": true }))
+            .await
+            .json::<BatchNotesResponse>();
+        assert!(response.committed);
+        assert_eq!(response.results[0].note.as_ref().unwrap().tags, ["import"]);
+        assert_eq!(count_notes().await.unwrap(), 2);
+        let note = server
+            .get("/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4")
+            .await
+            .json::<Note>();
+        assert_eq!((note.text.as_str(), note.version), ("one", 2));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_note() -> Result<()> {
         let db = init_test_db().await?;
 
         db.call(|conn| {
             conn.execute_batch(
-                "INSERT INTO notes (id, title, text) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1');",
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
             )
             .unwrap();
             Ok(())
@@ -146,13 +847,64 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn render_note() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute(
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', ?, uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
+                [r#"# Plan
+
+| a | b |
+|---|---|
+| 1 | 2 |
+
+- [x] done
+- [ ] todo
+
+[link](javascript:alert(1)) <img src="x" onerror="alert(1)">
+
+<script>alert(1)</script>"#],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db.clone()).await?;
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+
+        let response = server.get(&format!("{url}/render")).await.json::<serde_json::Value>();
+        let html = response["html"].as_str().unwrap();
+        assert!(html.contains("<h1>Plan</h1>"));
+        assert!(html.contains("<td>1</td>"));
+        assert!(html.contains(r#"type="checkbox""#));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+
+        assert!(server.get(url).await.json::<serde_json::Value>().get("html").is_none());
+        server.patch(url).json(&json!({ "text": "*changed*" })).await;
+        let note = server.get(&format!("{url}?html=true")).await.json::<Note>();
+        assert_eq!(note.html.as_deref(), Some("<p><em>changed</em></p>\n"));
+
+        let cached = db
+            .call(|conn| Ok(conn.query_row("SELECT version FROM note_html", [], |r| r.get::<_, u32>(0))?))
+            .await
+            .unwrap();
+        assert_eq!(cached, 2);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn update_note() -> Result<()> {
         let db = init_test_db().await?;
 
         db.call(|conn| {
             conn.execute_batch(
-                "INSERT INTO notes (id, title, text) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1');",
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
             )
             .unwrap();
             Ok(())
@@ -174,13 +926,159 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn patch_note() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db).await?;
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+
+        let response = server
+            .patch(url)
+            .content_type("application/merge-patch+json")
+            .bytes(
+                json!({ "title": null, "tags": ["a", "b"], "color": "red" })
+                    .to_string()
+                    .into(),
+            )
+            .await;
+        let note = response.json::<Note>();
+        assert_eq!((note.title.as_str(), note.text.as_str()), ("", "1"));
+        assert_eq!(note.tags, ["a", "b"]);
+
+        let response = server
+            .patch(url)
+            .content_type("application/json-patch+json")
+            .bytes(
+                json!([
+                    { "op": "test", "path": "/text", "value": "1" },
+                    { "op": "replace", "path": "/text", "value": "2" },
+                    { "op": "remove", "path": "/tags/0" },
+                    { "op": "add", "path": "/tags/-", "value": "c" },
+                    { "op": "copy", "from": "/text", "path": "/title" },
+                ])
+                .to_string()
+                .into(),
+            )
+            .await;
+        let note = response.json::<Note>();
+        assert_eq!((note.title.as_str(), note.text.as_str()), ("2", "2"));
+        assert_eq!(note.tags, ["b", "c"]);
+
+        for (operations, status) in [
+            (json!([{ "op": "test", "path": "/text", "value": "1" }]), 409),
+            (json!([{ "op": "remove", "path": "/tags/5" }]), 400),
+            (json!([{ "op": "add", "path": "/color", "value": "red" }]), 400),
+            (json!([{ "op": "replace", "path": "/title", "value": 1 }]), 400),
+        ] {
+            let response = server
+                .patch(url)
+                .content_type("application/json-patch+json")
+                .bytes(operations.to_string().into())
+                .expect_failure()
+                .await;
+            assert_eq!(response.status_code(), status);
+        }
+
+        // failed patches leave the note as it was, each patch bumped it for its fields and its tags
+        let note = server.get(url).await.json::<Note>();
+        assert_eq!((note.title.as_str(), note.text.as_str(), note.version), ("2", "2", 5));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conditional_requests() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db).await?;
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+
+        let response = server.get(url).await;
+        assert_eq!(response.header("etag"), "\"1\"");
+        assert_eq!(response.json::<Note>().version, 1);
+
+        let response = server
+            .get(url)
+            .add_header("if-none-match", "\"1\"")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 304);
+        assert!(response.as_bytes().is_empty());
+
+        // other representations of the version are cached apart
+        let response = server
+            .get(&format!("{url}?html=true"))
+            .add_header("if-none-match", "\"1\"")
+            .await;
+        assert_eq!(response.header("etag"), "\"1-html\"");
+        let response = server.get(&format!("{url}/render")).await;
+        assert_eq!(response.header("etag"), "\"1-rendered\"");
+        let response = server
+            .get(&format!("{url}/render"))
+            .add_header("if-none-match", "\"1-rendered\"")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 304);
+
+        let response = server
+            .patch(url)
+            .add_header("if-match", "\"1\"")
+            .json(&json!({ "text": "2" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.header("etag"), "\"2\"");
+
+        // a second client still on version 1
+        let response = server
+            .patch(url)
+            .add_header("if-match", "\"1\"")
+            .json(&json!({ "text": "3" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 412);
+        let response = server
+            .delete(url)
+            .add_header("if-match", "\"1\"")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 412);
+
+        let response = server.get(url).add_header("if-none-match", "\"1\"").await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.json::<Note>().text, "2");
+
+        let response = server.delete(url).add_header("if-match", "\"2\"").await;
+        assert_eq!(response.status_code(), 200);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn delete_note() -> Result<()> {
         let db = init_test_db().await?;
 
         db.call(|conn| {
             conn.execute(
-                "INSERT INTO notes (id, title, text) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1');",
+                "INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));",
                 []
             )
             .unwrap();
@@ -199,7 +1097,7 @@ mod tests {
 
         let count = db
             .call(|conn| {
-                conn.query_row::<u32, _, _>("select count(*) from notes", [], |r| r.get(0))
+                conn.query_row::<u32, _, _>("select count(*) from notes where deleted_at is null", [], |r| r.get(0))
                     .map_err(|e| e.into())
             })
             .await
@@ -210,6 +1108,269 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn trash() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (id, title, text, created_by, deleted_at) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000002'), 'expired', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), '2000-01-01 00:00:00');
+                UPDATE notes SET text = '2' WHERE title = 'expired';
+
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO tags (id, user_id, name) VALUES (uuid_blob('018f6139-0000-7000-8000-000000000001'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'work');
+                INSERT INTO note_shares (note_id, user_id, permission) SELECT id, uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'read' FROM notes;
+                INSERT INTO note_tags (note_id, tag_id) SELECT id, uuid_blob('018f6139-0000-7000-8000-000000000001') FROM notes;
+                INSERT INTO attachments (note_id, file_name, content_type, size, data) SELECT id, 'file', 'text/plain', 4, zeroblob(4) FROM notes;
+                INSERT INTO note_links (source_id, reference, target_id) SELECT id, 'other', id FROM notes;
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let server = test_server(db.clone()).await?;
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+        let trashed = "/api/v1/notes/trash/018f6138-5b4f-722d-97c5-29b927cedbd4";
+
+        server.delete(url).await;
+        let response = server.get(url).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        assert!(server
+            .get("/api/v1/notes")
+            .await
+            .json::<FindNotesResponse>()
+            .results
+            .is_empty());
+
+        let trash = server
+            .get("/api/v1/notes/trash")
+            .await
+            .json::<FindTrashResponse>()
+            .results;
+        assert_eq!(trash.len(), 2);
+        assert_eq!(trash[0].note.title, "first");
+        assert_eq!(trash[0].deleted_by, Some(TEST_USER_ID));
+
+        server.post(&format!("{trashed}/restore")).await;
+        assert_eq!(server.get(url).await.json::<Note>().text, "1");
+        let response = server.post(&format!("{trashed}/restore")).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+
+        // only trashed notes are purged
+        let response = server.delete(trashed).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        server.delete(url).await;
+        server.delete(trashed).await;
+        let response = server.post(&format!("{trashed}/restore")).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+
+        assert_eq!(super::handlers::purge_trash(&db, 30).await?, 1);
+        assert!(server
+            .get("/api/v1/notes/trash")
+            .await
+            .json::<FindTrashResponse>()
+            .results
+            .is_empty());
+
+        // the revisions, shares, tags, attachments and links went with the notes
+        let remaining = db
+            .call(|conn| {
+                [
+                    "note_revisions",
+                    "note_shares",
+                    "note_tags",
+                    "attachments",
+                    "note_links",
+                ]
+                .into_iter()
+                .map(|table| {
+                    conn.query_row(&format!("select count(*) from {table}"), [], |r| r.get::<_, u32>(0))
+                        .map(|count| (table, count))
+                })
+                .filter(|r| !matches!(r, Ok((_, 0))))
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(|e| e.into())
+            })
+            .await
+            .unwrap();
+        assert_eq!(remaining, []);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn note_revisions() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db).await?;
+
+        let note = server
+            .post("/api/v1/notes")
+            .json(&json!({ "title": "list", "text": "eggs\nmilk" }))
+            .await
+            .json::<Note>();
+        let url = format!("/api/v1/notes/{}", note.id);
+
+        server.patch(&url).json(&json!({ "text": "eggs\nbread\nmilk" })).await;
+        // unchanged, no revision
+        server.patch(&url).json(&json!({ "title": "list" })).await;
+        server.patch(&url).json(&json!({ "title": "groceries" })).await;
+
+        let revisions = server
+            .get(&format!("{url}/revisions"))
+            .await
+            .json::<FindRevisionsResponse>()
+            .results;
+        assert_eq!(revisions.iter().map(|r| r.revision).collect::<Vec<_>>(), [3, 2, 1]);
+        assert_eq!(revisions[0].updated_by, Some(TEST_USER_ID));
+
+        let first = server.get(&format!("{url}/revisions/1")).await.json::<NoteRevision>();
+        assert_eq!(first.text, "eggs\nmilk");
+
+        let diff = server.get(&format!("{url}/diff?from=1&to=3")).await.json::<NoteDiff>();
+        let ops: Vec<_> = diff.text.iter().map(|line| (line.op, line.value.as_str())).collect();
+        assert_eq!(
+            ops,
+            [
+                (DiffOp::Equal, "eggs"),
+                (DiffOp::Insert, "bread"),
+                (DiffOp::Equal, "milk")
+            ]
+        );
+        assert_eq!(diff.text[2].old_line, Some(2));
+        assert_eq!(diff.text[2].new_line, Some(3));
+        assert_eq!(diff.title.len(), 2);
+
+        let response = server
+            .post(&format!("{url}/revisions/1/restore"))
+            .add_header("if-match", "\"1\"")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 412);
+
+        let restored = server.post(&format!("{url}/revisions/1/restore")).await.json::<Note>();
+        assert_eq!(
+            (restored.title.as_str(), restored.text.as_str()),
+            ("list", "eggs\nmilk")
+        );
+        let latest = server.get(&format!("{url}/revisions/4")).await.json::<NoteRevision>();
+        assert_eq!(latest.text, "eggs\nmilk");
+
+        let response = server.get(&format!("{url}/revisions/9")).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        let response = server.get(&format!("{url}/diff?from=1&to=9")).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn other_users_notes() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let mut server = test_server(db.clone()).await?;
+        authenticate(&db, &mut server, OTHER_USER_ID).await?;
+
+        let response = server.get("/api/v1/notes").await;
+        assert!(response.json::<FindNotesResponse>().results.is_empty());
+
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+        let response = server.get(url).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        let response = server.patch(url).json(&json!({ "text": "2" })).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        let response = server.delete(url).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+
+        // the owner still sees it unchanged
+        authenticate(&db, &mut server, TEST_USER_ID).await?;
+        let response = server.get(url).await;
+        assert_eq!(response.json::<Note>().text, "1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn share_note() -> Result<()> {
+        let db = init_test_db().await?;
+
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-29b927cedbd4'), 'first', '1', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let owner = test_server(db.clone()).await?;
+        let mut other = test_server(db.clone()).await?;
+        authenticate(&db, &mut other, OTHER_USER_ID).await?;
+
+        let url = "/api/v1/notes/018f6138-5b4f-722d-97c5-29b927cedbd4";
+        let shares = format!("{url}/shares");
+
+        let response = owner
+            .post(&shares)
+            .json(&json!({ "email": "other@mail.com", "permission": "read" }))
+            .await;
+        assert_eq!(response.status_code(), 201);
+
+        // read only
+        other.get(url).await;
+        let shared = other
+            .get("/api/v1/notes/shared")
+            .await
+            .json::<FindSharedNotesResponse>();
+        assert_eq!(shared.results.len(), 1);
+        assert_eq!(shared.results[0].permission, SharePermission::Read);
+        let response = other.patch(url).json(&json!({ "text": "2" })).expect_failure().await;
+        assert_eq!(response.status_code(), 403);
+
+        // upgraded to write, but only the owner deletes and shares
+        owner
+            .post(&shares)
+            .json(&json!({ "email": "other@mail.com", "permission": "write" }))
+            .await;
+        let response = other.patch(url).json(&json!({ "text": "2" })).await;
+        assert_eq!(response.json::<Note>().text, "2");
+        let response = other.delete(url).expect_failure().await;
+        assert_eq!(response.status_code(), 403);
+        let response = other
+            .post(&shares)
+            .json(&json!({ "email": "fake@mail.com", "permission": "write" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 403);
+
+        let collaborators = other.get(&shares).await.json::<FindCollaboratorsResponse>();
+        assert_eq!(collaborators.results.len(), 1);
+        assert_eq!(collaborators.results[0].user_id, OTHER_USER_ID);
+
+        owner.delete(&format!("{shares}/{OTHER_USER_ID}")).await;
+        let response = other.get(url).expect_failure().await;
+        assert_eq!(response.status_code(), 404);
+        Ok(())
+    }
+
     async fn test_server(db: DB) -> Result<TestServer> {
         crate::tests::test_server(db, super::router).await
     }
@@ -1,8 +1,11 @@
-use aide::operation::OperationIo;
+use std::marker::PhantomData;
+
+use aide::operation::{set_body, OperationInput, OperationIo};
 use aide::OperationOutput;
-use axum::response::IntoResponse;
+use axum::{extract::Request, response::IntoResponse};
 use axum_macros::{FromRequest, FromRequestParts};
 use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::Serialize;
 
 pub use aide;
@@ -33,6 +36,44 @@ pub struct Query<T>(pub T);
 #[aide(input_with = "axum::extract::Path<T>", json_schema)]
 pub struct Path<T>(pub T);
 
+/// `multipart/form-data` body documented with the schema of `T`, fields are read from the stream by the handler.
+pub struct Multipart<T>(pub axum::extract::Multipart, pub PhantomData<T>);
+
+impl<T, S: Send + Sync> axum::extract::FromRequest<S> for Multipart<T> {
+    type Rejection = crate::Error;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let multipart =
+            <axum::extract::Multipart as axum::extract::FromRequest<S>>::from_request(request, state).await?;
+        Ok(Self(multipart, PhantomData))
+    }
+}
+
+impl<T: JsonSchema> OperationInput for Multipart<T> {
+    fn operation_input(ctx: &mut aide::generate::GenContext, operation: &mut aide::openapi::Operation) {
+        let schema = ctx.schema.subschema_for::<T>().into_object();
+        set_body(
+            ctx,
+            operation,
+            aide::openapi::RequestBody {
+                content: IndexMap::from_iter([(
+                    "multipart/form-data".into(),
+                    aide::openapi::MediaType {
+                        schema: Some(aide::openapi::SchemaObject {
+                            json_schema: schema.into(),
+                            example: None,
+                            external_docs: None,
+                        }),
+                        ..Default::default()
+                    },
+                )]),
+                required: true,
+                ..Default::default()
+            },
+        );
+    }
+}
+
 impl OperationOutput for crate::Error {
     type Inner = ();
 
@@ -71,3 +112,14 @@ impl OperationOutput for crate::Error {
         }
     }
 }
+
+pub trait TransformOperationExt {
+    /// Documents an error status of the operation with the `ErrorResponse` schema.
+    fn error<const N: u16>(self, description: &str) -> Self;
+}
+
+impl TransformOperationExt for aide::transform::TransformOperation<'_> {
+    fn error<const N: u16>(self, description: &str) -> Self {
+        self.response_with::<N, crate::Error, _>(|r| r.description(description))
+    }
+}
@@ -0,0 +1,222 @@
+use std::{marker::PhantomData, str::FromStr};
+
+use aide::{
+    generate::GenContext,
+    openapi::{Operation, ReferenceOr, Response, StatusCode},
+    operation::OperationInput,
+    OperationOutput,
+};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx::{BaseParams, Ctx, Role},
+    Error,
+};
+
+/// Name of the security scheme registered in the OpenAPI spec.
+pub const SECURITY_SCHEME: &str = "bearer";
+
+/// Granted to users by their role and to API tokens by their scopes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Permission {
+    #[serde(rename = "notes:read")]
+    NotesRead,
+    #[serde(rename = "notes:write")]
+    NotesWrite,
+    #[serde(rename = "users:manage")]
+    UsersManage,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotesRead => "notes:read",
+            Self::NotesWrite => "notes:write",
+            Self::UsersManage => "users:manage",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "notes:read" => Ok(Self::NotesRead),
+            "notes:write" => Ok(Self::NotesWrite),
+            "users:manage" => Ok(Self::UsersManage),
+            _ => Err(Error::Unexpected(format!("Unknown permission: {s}"))),
+        }
+    }
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Self::Admin => &[NotesRead, NotesWrite, UsersManage],
+            Self::Member => &[NotesRead, NotesWrite],
+            Self::Guest => &[NotesRead],
+        }
+    }
+}
+
+impl Ctx {
+    /// The user's role must grant `permission`, and so must the scopes when authenticated by an API token.
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        let granted = self
+            .user
+            .as_ref()
+            .is_some_and(|user| user.role.permissions().contains(&permission));
+        let in_scope = self.scopes.as_ref().is_none_or(|scopes| scopes.contains(&permission));
+
+        granted && in_scope
+    }
+}
+
+/// A permission required by a route, see [`Authorized`].
+pub trait Policy: Send + Sync + 'static {
+    const PERMISSION: Permission;
+}
+
+macro_rules! policies {
+    ($($name:ident),* $(,)?) => {
+        $(
+            pub struct $name;
+
+            impl Policy for $name {
+                const PERMISSION: Permission = Permission::$name;
+            }
+        )*
+    };
+}
+
+pub mod policies {
+    use super::{Permission, Policy};
+
+    policies!(NotesRead, NotesWrite, UsersManage);
+}
+
+/// [`BaseParams`] of a caller granted `P`, see [`Ctx::has_permission`].
+/// The check runs during extraction, before the handler body, and is documented
+/// as a security requirement plus 401/403 responses of the operation.
+/// ```rust
+/// async fn delete_note(Authorized(base, _): Authorized<NotesWrite>) -> impl IntoApiResponse { }
+/// ```
+pub struct Authorized<P: Policy>(pub BaseParams, pub PhantomData<P>);
+
+impl<S, P> FromRequestParts<S> for Authorized<P>
+where
+    S: Send + Sync,
+    P: Policy,
+{
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let base = BaseParams::from_request_parts(parts, state).await?;
+        if !base.ctx.has_permission(P::PERMISSION) {
+            return Err(axum::response::IntoResponse::into_response(Error::Forbidden));
+        }
+        Ok(Self(base, PhantomData))
+    }
+}
+
+// Responses are added in `operation_input`: aide does not forward `inferred_early_responses`
+// of extractors that are part of a handler's argument tuple.
+impl<P: Policy> OperationInput for Authorized<P> {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        operation.security.push(IndexMap::from_iter([(
+            SECURITY_SCHEME.to_string(),
+            vec![P::PERMISSION.as_str().to_string()],
+        )]));
+
+        let Some(res) = Error::operation_response(ctx, operation) else {
+            return;
+        };
+
+        let unauthorized = Response {
+            description: "Not authenticated".into(),
+            ..res.clone()
+        };
+        let forbidden = Response {
+            description: format!("Caller lacks the `{}` permission", P::PERMISSION.as_str()),
+            ..res
+        };
+
+        let responses = operation.responses.get_or_insert_with(Default::default);
+        for (status, res) in [(401, unauthorized), (403, forbidden)] {
+            responses
+                .responses
+                .entry(StatusCode::Code(status))
+                .or_insert(ReferenceOr::Item(res));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+    use uuid::{uuid, Uuid};
+
+    use crate::{
+        app::{create, AppParams},
+        auth::jwt::Keys,
+        db::init_test_db,
+        errors::Result,
+        tests::{authenticate, test_config, test_server},
+    };
+
+    const GUEST_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000003");
+
+    #[tokio::test]
+    async fn guests_are_read_only() -> Result<()> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute(
+                "INSERT INTO users (id, email, role, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000003'), 'guest@mail.com', 'guest', 'active');",
+                [],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let mut server = test_server(db.clone(), crate::notes::router).await?;
+        authenticate(&db, &mut server, GUEST_USER_ID).await?;
+
+        server.get("/api/v1/notes").await;
+        let response = server
+            .post("/api/v1/notes")
+            .json(&json!({ "text": "hello", "title": "world" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 403);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn documents_required_permission() -> Result<()> {
+        let db = init_test_db().await?;
+        let config = test_config();
+        let (_, api) = create(AppParams {
+            db,
+            keys: Arc::new(Keys::new(&config.jwt_keys)?),
+            config: Arc::new(config),
+            router: crate::notes::router,
+        })
+        .await?;
+
+        let spec = serde_json::to_value(&api).unwrap();
+        let post = &spec["paths"]["/api/v1/notes"]["post"];
+        assert_eq!(post["security"], json!([{ "bearer": ["notes:write"] }]));
+        assert!(post["responses"]["403"].is_object());
+        assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+        Ok(())
+    }
+}
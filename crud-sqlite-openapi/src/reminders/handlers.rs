@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Row};
+use uuid::Uuid;
+
+use crate::{ctx::BaseParams, db, Error, Result, DB};
+
+use super::{FindNotifications, FindNotificationsResponse, Notification};
+
+impl<'a> TryFrom<&Row<'a>> for Notification {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            title: row.get(2)?,
+            due_at: row.get(3)?,
+            remind_at: row.get(4)?,
+            created_at: row.get(5)?,
+            read_at: row.get(6)?,
+        })
+    }
+}
+
+/// Notifies the owners of notes with a pending reminder at or before `now`, returns how many fired.
+/// Reminders of trashed notes wait until the note is restored.
+pub async fn fire_reminders(db: &DB, now: DateTime<Utc>) -> Result<usize> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let fired = tx.execute(
+            r#"INSERT INTO notifications (user_id, note_id, title, due_at, remind_at)
+            SELECT created_by, id, title, due_at, remind_at FROM notes
+            WHERE remind_at <= ? AND reminded_at IS NULL AND deleted_at IS NULL AND created_by IS NOT NULL"#,
+            params![now],
+        )?;
+        tx.execute(
+            r#"UPDATE notes SET reminded_at = ?1
+            WHERE remind_at <= ?1 AND reminded_at IS NULL AND deleted_at IS NULL"#,
+            params![now],
+        )?;
+        tx.commit()?;
+        Ok(fired)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Time of the earliest pending reminder.
+pub async fn next_reminder(db: &DB) -> Result<Option<DateTime<Utc>>> {
+    db.call(|conn| {
+        conn.query_row(
+            r#"SELECT min(remind_at) FROM notes
+            WHERE remind_at IS NOT NULL AND reminded_at IS NULL AND deleted_at IS NULL"#,
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn find_notifications(
+    FindNotifications { unread }: FindNotifications,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<FindNotificationsResponse> {
+    db.call(move |conn| {
+        let notifications = conn
+            .prepare(
+                r#"SELECT id, note_id, title, due_at, remind_at, created_at, read_at FROM notifications
+                WHERE user_id = ? AND (NOT ? OR read_at IS NULL)
+                ORDER BY id DESC"#,
+            )?
+            .query_map(params![ctx.get_user_id(), unread], |row| Notification::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindNotificationsResponse { results: notifications })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+pub async fn read_notification(notification_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<Notification> {
+    db.call(move |conn| {
+        conn.query_row(
+            r#"UPDATE notifications SET read_at = coalesce(read_at, ?)
+            WHERE id = ? AND user_id = ?
+            RETURNING id, note_id, title, due_at, remind_at, created_at, read_at"#,
+            params![Utc::now(), notification_id, ctx.get_user_id()],
+            |row| Notification::try_from(row),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "Notification not found"))
+    .map_err(Error::from)
+}
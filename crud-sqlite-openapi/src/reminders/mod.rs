@@ -0,0 +1,160 @@
+mod handlers;
+mod model;
+mod routes;
+
+use std::future::Future;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use lazy_static::lazy_static;
+use tokio::sync::Notify;
+
+use model::*;
+
+use crate::{openapi::aide::axum::ApiRouter, state::AppState, DB};
+
+/// Longest the worker sleeps, so that it keeps up with changes of the system clock.
+const MAX_SLEEP: TimeDelta = TimeDelta::hours(1);
+/// Delay before the worker looks for reminders again after a database error.
+const RETRY_DELAY: TimeDelta = TimeDelta::minutes(1);
+
+lazy_static! {
+    static ref RESCHEDULE: Notify = Notify::new();
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new().merge(routes::router(state.clone()))
+}
+
+/// Time source of the reminder worker.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn sleep_until(&self, deadline: DateTime<Utc>) -> impl Future<Output = ()> + Send;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep_until(&self, deadline: DateTime<Utc>) {
+        tokio::time::sleep((deadline - Utc::now()).to_std().unwrap_or_default()).await
+    }
+}
+
+/// Wakes the worker to look for the next reminder, call it once a reminder changed.
+pub(crate) fn reschedule() {
+    RESCHEDULE.notify_one();
+}
+
+/// Fires due reminders, then sleeps until the next one or a [`reschedule`].
+/// Fired reminders are marked in the same transaction as their notifications, so restarts never fire them twice.
+pub fn spawn_reminders(db: DB, clock: impl Clock) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let wake_at = match handlers::fire_reminders(&db, clock.now()).await {
+                Ok(fired) => {
+                    if fired > 0 {
+                        tracing::info!(fired, "fired reminders");
+                    }
+                    let now = clock.now();
+                    match handlers::next_reminder(&db).await {
+                        Ok(next) => next.map_or(now + MAX_SLEEP, |next| next.min(now + MAX_SLEEP)),
+                        Err(error) => {
+                            tracing::error!(?error, "failed to find the next reminder");
+                            now + RETRY_DELAY
+                        }
+                    }
+                }
+                // a due reminder would be retried right away otherwise
+                Err(error) => {
+                    tracing::error!(?error, "failed to fire reminders");
+                    clock.now() + RETRY_DELAY
+                }
+            };
+            tokio::select! {
+                _ = clock.sleep_until(wake_at) => {}
+                _ = RESCHEDULE.notified() => {}
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use chrono::TimeZone;
+    use rusqlite::params;
+    use tokio::sync::watch;
+
+    use super::*;
+    use crate::{db::init_test_db, errors::Result};
+
+    /// Stands still until advanced.
+    #[derive(Clone)]
+    struct ManualClock(Arc<watch::Sender<DateTime<Utc>>>);
+
+    impl ManualClock {
+        fn advance(&self, delta: TimeDelta) {
+            self.0.send_modify(|now| *now += delta);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.borrow()
+        }
+
+        async fn sleep_until(&self, deadline: DateTime<Utc>) {
+            let mut now = self.0.subscribe();
+            now.wait_for(|now| *now >= deadline).await.ok();
+        }
+    }
+
+    async fn notifications(db: &DB) -> i64 {
+        db.call(|conn| Ok(conn.query_row("SELECT count(*) FROM notifications", [], |row| row.get(0))?))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fires_reminders_once() -> Result<()> {
+        let db = init_test_db().await?;
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+        let clock = ManualClock(Arc::new(watch::Sender::new(start)));
+
+        db.call(move |conn| {
+            conn.execute(
+                r#"INSERT INTO notes (title, text, remind_at, created_by)
+                VALUES ('first', '', ?, uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'))"#,
+                params![start + TimeDelta::minutes(10)],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let worker = spawn_reminders(db.clone(), clock.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(notifications(&db).await, 0);
+
+        clock.advance(TimeDelta::minutes(10));
+        for _ in 0..100 {
+            if notifications(&db).await > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(notifications(&db).await, 1);
+
+        // a restarted worker starts by firing what's due
+        worker.abort();
+        assert_eq!(handlers::fire_reminders(&db, clock.now()).await?, 0);
+        assert_eq!(notifications(&db).await, 1);
+        Ok(())
+    }
+}
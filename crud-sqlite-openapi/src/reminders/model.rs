@@ -0,0 +1,30 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A reminder that fired, sent to the owner of the note.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Notification {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    /// Title of the note when the reminder fired.
+    pub title: String,
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub remind_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Unset until marked as read.
+    pub read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindNotifications {
+    /// Only notifications not marked as read.
+    #[serde(default)]
+    pub unread: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindNotificationsResponse {
+    /// Most recent first.
+    pub results: Vec<Notification>,
+}
@@ -0,0 +1,140 @@
+use crate::{
+    openapi::{
+        aide::axum::{
+            routing::{get_with, post_with},
+            ApiRouter, IntoApiResponse,
+        },
+        Json, Path, Query, TransformOperationExt,
+    },
+    policy::{policies::NotesRead, Authorized},
+    state::AppState,
+};
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{handlers, FindNotifications};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NotificationIdPath {
+    notification_id: Uuid,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/notifications",
+            get_with(find_notifications, |t| {
+                t.description("Reminders of the caller's notes that fired.")
+            }),
+        )
+        .api_route(
+            "/api/v1/notifications/{notification_id}/read",
+            post_with(read_notification, |t| {
+                t.description("Marks the notification as read.")
+                    .error::<404>("Notification not found")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn find_notifications(
+    Query(args): Query<FindNotifications>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::find_notifications(args, base).await.map(Json)
+}
+
+async fn read_notification(
+    Path(NotificationIdPath { notification_id }): Path<NotificationIdPath>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> impl IntoApiResponse {
+    handlers::read_notification(notification_id, base).await.map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+    use chrono::{TimeDelta, Utc};
+    use serde_json::json;
+
+    use crate::{
+        db::{init_test_db, DB},
+        errors::Result,
+        notes::Note,
+        reminders::{handlers::fire_reminders, FindNotificationsResponse, Notification},
+        state::AppState,
+    };
+
+    #[tokio::test]
+    async fn notifications() -> Result<()> {
+        let db = init_test_db().await?;
+        let server = test_server(db.clone()).await?;
+        let now = Utc::now();
+
+        let note = server
+            .post("/api/v1/notes")
+            .json(&json!({
+                "title": "Taxes",
+                "text": "",
+                "due_at": now + TimeDelta::days(1),
+                "remind_at": now - TimeDelta::minutes(1),
+            }))
+            .await
+            .json::<Note>();
+        assert_eq!(
+            note.due_at.map(|due_at| due_at.timestamp()),
+            Some((now + TimeDelta::days(1)).timestamp())
+        );
+        assert_eq!(fire_reminders(&db, now).await?, 1);
+        assert_eq!(fire_reminders(&db, now).await?, 0);
+
+        let notifications = server
+            .get("/api/v1/notifications?unread=true")
+            .await
+            .json::<FindNotificationsResponse>();
+        let [notification] = notifications.results.as_slice() else {
+            panic!("expected one notification, got {notifications:?}");
+        };
+        assert_eq!((notification.note_id, notification.title.as_str()), (note.id, "Taxes"));
+
+        let read = server
+            .post(&format!("/api/v1/notifications/{}/read", notification.id))
+            .await
+            .json::<Notification>();
+        assert!(read.read_at.is_some());
+        let unread = server
+            .get("/api/v1/notifications?unread=true")
+            .await
+            .json::<FindNotificationsResponse>();
+        assert!(unread.results.is_empty());
+
+        // other fields keep the fired reminder, a new time fires again
+        let url = format!("/api/v1/notes/{}", note.id);
+        server.patch(&url).json(&json!({ "title": "Tax return" })).await;
+        assert_eq!(fire_reminders(&db, now).await?, 0);
+        server
+            .patch(&url)
+            .json(&json!({ "remind_at": now - TimeDelta::seconds(1) }))
+            .await;
+        assert_eq!(fire_reminders(&db, now).await?, 1);
+        server.patch(&url).json(&json!({ "remind_at": null })).await;
+        assert_eq!(server.get(&url).await.json::<Note>().remind_at, None);
+
+        server
+            .post("/api/v1/notifications/018f6138-5b4f-722d-97c5-29b927cedbd4/read")
+            .expect_failure()
+            .await
+            .assert_status_not_found();
+        Ok(())
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, |state: AppState| {
+            super::router(state.clone()).merge(crate::notes::router(state))
+        })
+        .await
+    }
+}
@@ -1,8 +1,13 @@
+use std::sync::Arc;
+
 use axum::extract::FromRef;
 
-use crate::db::DB;
+use crate::{auth::jwt::Keys, config::Config, db::DB};
 
 #[derive(FromRef, Clone)]
 pub struct AppState {
     pub conn: DB,
+    pub config: Arc<Config>,
+    /// Parsed from [`Config::jwt_keys`] at startup.
+    pub keys: Arc<Keys>,
 }
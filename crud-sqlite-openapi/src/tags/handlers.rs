@@ -0,0 +1,133 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+use crate::{ctx::BaseParams, db, Error, Result};
+
+use super::{FindTagsResponse, MergeTag, RenameTag, Tag};
+
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Trims a tag name. Commas are reserved as the separator of tag filters.
+pub fn normalize_tag(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH || name.contains(',') {
+        return Err(Error::Validation(format!(
+            "Tag names must be 1 to {MAX_TAG_LENGTH} characters long, without commas"
+        )));
+    }
+    Ok(name.to_string())
+}
+
+const SELECT_TAGS: &str = r#"SELECT tags.id, tags.name, count(notes.id) FROM tags
+    LEFT JOIN note_tags ON note_tags.tag_id = tags.id
+    LEFT JOIN notes ON notes.id = note_tags.note_id AND notes.deleted_at IS NULL"#;
+
+impl<'a> TryFrom<&rusqlite::Row<'a>> for Tag {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row<'a>) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            notes: row.get(2)?,
+        })
+    }
+}
+
+/// Tag of `user_id`, `NotFound` for other users' tags.
+fn tag(conn: &Connection, tag_id: Uuid, user_id: Option<Uuid>) -> std::result::Result<Tag, tokio_rusqlite::Error> {
+    conn.query_row(
+        &format!("{SELECT_TAGS} WHERE tags.id = ? AND tags.user_id = ? GROUP BY tags.id"),
+        params![tag_id, user_id],
+        |row| Tag::try_from(row),
+    )
+    .optional()?
+    .ok_or_else(|| Error::NotFound("Tag not found".into()).into())
+}
+
+/// Bumps the version of the notes tagged with `tag_id`, their tags change along with it.
+fn bump_tagged_notes(conn: &Connection, tag_id: Uuid) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE notes SET version = version + 1 WHERE id IN (SELECT note_id FROM note_tags WHERE tag_id = ?)",
+        params![tag_id],
+    )
+}
+
+pub async fn find_tags(BaseParams { db, ctx }: BaseParams) -> Result<FindTagsResponse> {
+    db.call(move |conn| {
+        let tags = conn
+            .prepare(&format!(
+                "{SELECT_TAGS} WHERE tags.user_id = ? GROUP BY tags.id ORDER BY tags.name"
+            ))?
+            .query_map(params![ctx.get_user_id()], |row| Tag::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindTagsResponse { results: tags })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Renames a tag on all of its notes. Fails when another tag already has the name, those are merged instead.
+pub async fn rename_tag(
+    tag_id: Uuid,
+    RenameTag { name }: RenameTag,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<Tag> {
+    let name = normalize_tag(&name)?;
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let tx = conn.transaction()?;
+        tag(&tx, tag_id, user_id)?;
+
+        let taken = tx
+            .query_row(
+                "SELECT 1 FROM tags WHERE user_id = ? AND name = ? AND id != ?",
+                params![user_id, name, tag_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if taken.is_some() {
+            return Err(Error::Conflict(format!("Tag {name} already exists, merge into it instead")).into());
+        }
+
+        tx.execute("UPDATE tags SET name = ? WHERE id = ?", params![name, tag_id])?;
+        bump_tagged_notes(&tx, tag_id)?;
+
+        let tag = tag(&tx, tag_id, user_id)?;
+        tx.commit()?;
+        Ok(tag)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Moves the notes of a tag to another one and deletes it.
+pub async fn merge_tag(tag_id: Uuid, MergeTag { into }: MergeTag, BaseParams { db, ctx }: BaseParams) -> Result<Tag> {
+    if tag_id == into {
+        return Err(Error::Validation("Can't merge a tag into itself".into()));
+    }
+
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let tx = conn.transaction()?;
+        tag(&tx, tag_id, user_id)?;
+        tag(&tx, into, user_id)?;
+
+        bump_tagged_notes(&tx, tag_id)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id) SELECT note_id, ?2 FROM note_tags WHERE tag_id = ?1",
+            params![tag_id, into],
+        )?;
+        tx.execute("DELETE FROM note_tags WHERE tag_id = ?", params![tag_id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?", params![tag_id])?;
+
+        let tag = tag(&tx, into, user_id)?;
+        tx.commit()?;
+        Ok(tag)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
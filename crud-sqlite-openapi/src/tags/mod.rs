@@ -0,0 +1,12 @@
+mod handlers;
+mod model;
+mod routes;
+
+pub use handlers::normalize_tag;
+use model::*;
+
+use crate::{openapi::aide::axum::ApiRouter, state::AppState};
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new().merge(routes::router(state.clone()))
+}
@@ -0,0 +1,28 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Tag {
+    pub id: Uuid,
+    pub name: String,
+    /// Number of notes with the tag, leaving out the trash.
+    pub notes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindTagsResponse {
+    /// In alphabetical order.
+    pub results: Vec<Tag>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RenameTag {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MergeTag {
+    /// Tag that takes over the notes of the merged one.
+    pub into: Uuid,
+}
@@ -0,0 +1,182 @@
+use crate::{
+    openapi::{
+        aide::axum::{
+            routing::{get_with, patch_with, post_with},
+            ApiRouter, IntoApiResponse,
+        },
+        Json, Path, TransformOperationExt,
+    },
+    policy::{
+        policies::{NotesRead, NotesWrite},
+        Authorized,
+    },
+    state::AppState,
+};
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{handlers, MergeTag, RenameTag, Tag};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TagIdPath {
+    tag_id: Uuid,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/tags",
+            get_with(find_tags, |t| {
+                t.description("The caller's tags with how many notes have them.")
+            }),
+        )
+        .api_route(
+            "/api/v1/tags/{tag_id}",
+            patch_with(rename_tag, |t| {
+                t.description("Renames a tag on all of its notes.")
+                    .error::<400>("Invalid name")
+                    .error::<404>("Tag not found")
+                    .error::<409>("Another tag has the name")
+            }),
+        )
+        .api_route(
+            "/api/v1/tags/{tag_id}/merge",
+            post_with(merge_tag, |t| {
+                t.description("Moves the tag's notes to the `into` tag and deletes it.")
+                    .error::<400>("Merging a tag into itself")
+                    .error::<404>("Tag not found")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn find_tags(Authorized(base, _): Authorized<NotesRead>) -> impl IntoApiResponse {
+    handlers::find_tags(base).await.map(Json)
+}
+
+async fn rename_tag(
+    Path(TagIdPath { tag_id }): Path<TagIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<RenameTag>,
+) -> impl IntoApiResponse {
+    handlers::rename_tag(tag_id, args, base).await.map(Json::<Tag>)
+}
+
+async fn merge_tag(
+    Path(TagIdPath { tag_id }): Path<TagIdPath>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    Json(args): Json<MergeTag>,
+) -> impl IntoApiResponse {
+    handlers::merge_tag(tag_id, args, base).await.map(Json::<Tag>)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+    use serde_json::json;
+    use uuid::{uuid, Uuid};
+
+    use crate::{
+        db::{init_test_db, DB},
+        errors::Result,
+        tags::{FindTagsResponse, Tag},
+        tests::authenticate,
+    };
+
+    const OTHER_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000002");
+
+    #[tokio::test]
+    async fn rename_and_merge_tags() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let tags = server.get("/api/v1/tags").await.json::<FindTagsResponse>().results;
+        let counts: Vec<_> = tags.iter().map(|tag| (tag.name.as_str(), tag.notes)).collect();
+        assert_eq!(counts, [("recipes", 2), ("Rust", 1), ("rust-lang", 1)]);
+        let (recipes, rust, rust_lang) = (tags[0].id, tags[1].id, tags[2].id);
+
+        let response = server
+            .patch(&format!("/api/v1/tags/{rust_lang}"))
+            .json(&json!({ "name": "RUST" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 409);
+
+        let response = server
+            .patch(&format!("/api/v1/tags/{rust}"))
+            .json(&json!({ "name": " rust " }))
+            .await;
+        assert_eq!(response.json::<Tag>().name, "rust");
+
+        let response = server
+            .post(&format!("/api/v1/tags/{rust_lang}/merge"))
+            .json(&json!({ "into": rust }))
+            .await;
+        // the note had both
+        assert_eq!(response.json::<Tag>().notes, 1);
+        let tags = server.get("/api/v1/tags").await.json::<FindTagsResponse>().results;
+        assert_eq!(tags.iter().map(|tag| tag.id).collect::<Vec<_>>(), [recipes, rust]);
+
+        // renaming and merging changed the tags of axum
+        let versions = db
+            .call(|conn| {
+                Ok(conn
+                    .prepare("SELECT title, version FROM notes ORDER BY title")?
+                    .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, u32>(1)?)))?
+                    .collect::<std::result::Result<Vec<_>, _>>()?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(versions, [("axum".into(), 3), ("bread".into(), 1), ("cake".into(), 1)]);
+
+        // tags are private
+        let mut other = test_server(db.clone()).await?;
+        authenticate(&db, &mut other, OTHER_USER_ID).await?;
+        assert!(other
+            .get("/api/v1/tags")
+            .await
+            .json::<FindTagsResponse>()
+            .results
+            .is_empty());
+        let response = other
+            .patch(&format!("/api/v1/tags/{rust}"))
+            .json(&json!({ "name": "mine" }))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 404);
+        Ok(())
+    }
+
+    async fn test_db() -> Result<DB> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000001'), 'bread', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000002'), 'cake', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000003'), 'axum', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'));
+                INSERT INTO tags (id, user_id, name) VALUES (uuid_blob('018f6139-0000-7000-8000-000000000001'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'recipes');
+                INSERT INTO tags (id, user_id, name) VALUES (uuid_blob('018f6139-0000-7000-8000-000000000002'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'Rust');
+                INSERT INTO tags (id, user_id, name) VALUES (uuid_blob('018f6139-0000-7000-8000-000000000003'), uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'rust-lang');
+                INSERT INTO note_tags (note_id, tag_id) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000001'), uuid_blob('018f6139-0000-7000-8000-000000000001'));
+                INSERT INTO note_tags (note_id, tag_id) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000002'), uuid_blob('018f6139-0000-7000-8000-000000000001'));
+                INSERT INTO note_tags (note_id, tag_id) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000003'), uuid_blob('018f6139-0000-7000-8000-000000000002'));
+                INSERT INTO note_tags (note_id, tag_id) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000003'), uuid_blob('018f6139-0000-7000-8000-000000000003'));
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        Ok(db)
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, super::router).await
+    }
+}
@@ -0,0 +1,579 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use axum::body::Bytes;
+use futures_util::{stream, Stream};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    ctx::BaseParams,
+    db,
+    links::update_links,
+    notes::{load_details, normalize_tags, replace_tags, set_tags, Note},
+    reminders, Error, Result,
+};
+
+use super::{ImportNotesResponse, NoteRecord, TransferFormat};
+
+/// Notes read from the database per chunk of an export.
+const PAGE_SIZE: u32 = 100;
+
+impl From<Note> for NoteRecord {
+    fn from(note: Note) -> Self {
+        Self {
+            id: Some(note.id),
+            title: note.title,
+            text: note.text,
+            created_at: Some(note.created_at),
+            updated_at: note.updated_at,
+            due_at: note.due_at,
+            remind_at: note.remind_at,
+            tags: note.tags,
+        }
+    }
+}
+
+/// Streams the caller's notes in the order they were created, trashed notes are left out.
+pub fn export_notes(format: TransferFormat, BaseParams { db, ctx }: BaseParams) -> impl Stream<Item = Result<Bytes>> {
+    let user_id = ctx.get_user_id();
+
+    stream::try_unfold((Some(Encoder::new(format)), None), move |(encoder, after)| {
+        let db = db.clone();
+        async move {
+            let Some(mut encoder) = encoder else {
+                return Ok(None);
+            };
+            let notes = db
+                .call(move |conn| {
+                    let mut notes = conn
+                        .prepare_cached(
+                            r#"SELECT id, title, text, created_at, created_by, updated_at, updated_by FROM notes
+                            WHERE created_by = ?1 AND deleted_at IS NULL AND (?2 IS NULL OR id > ?2)
+                            ORDER BY id
+                            LIMIT ?3"#,
+                        )?
+                        .query_map(params![user_id, after, PAGE_SIZE], |row| Note::try_from(row))?
+                        .collect::<std::result::Result<Vec<_>, _>>()?;
+                    load_details(conn, notes.iter_mut())?;
+                    Ok(notes)
+                })
+                .await
+                .map_err(db::Error::from)?;
+
+            let Some(last) = notes.last().map(|note| note.id) else {
+                return Ok(Some((Bytes::from(encoder.finish()?), (None, None))));
+            };
+            let chunk = encoder.encode(notes.into_iter().map(NoteRecord::from))?;
+            Ok(Some((Bytes::from(chunk), (Some(encoder), Some(last)))))
+        }
+    })
+}
+
+/// Creates notes that don't exist yet and updates the caller's notes by id, all in one transaction.
+/// `max_bytes` bounds the unpacked size of a zip.
+pub async fn import_notes(
+    format: TransferFormat,
+    content: Bytes,
+    max_bytes: u64,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<ImportNotesResponse> {
+    let records = parse_records(format, &content, max_bytes)?;
+
+    db.call(move |conn| {
+        let user_id = ctx.get_user_id();
+        let tx = conn.transaction()?;
+        let mut response = ImportNotesResponse::default();
+        let mut reminders_changed = false;
+
+        for NoteRecord {
+            id,
+            title,
+            text,
+            created_at,
+            updated_at,
+            due_at,
+            remind_at,
+            tags,
+        } in records
+        {
+            let tags = normalize_tags(tags)?;
+            // ids are time ordered, others would sort out of place
+            let id = id.filter(|id| id.get_version_num() == 7);
+            let existing = match id {
+                Some(id) => tx
+                    .query_row(
+                        r#"SELECT id, title, text, created_at, created_by, updated_at, updated_by, deleted_at IS NOT NULL
+                        FROM notes WHERE id = ?"#,
+                        params![id],
+                        |row| Ok((Note::try_from(row)?, row.get::<_, bool>(7)?)),
+                    )
+                    .optional()?,
+                None => None,
+            };
+
+            match existing {
+                None => {
+                    let note_id = id.unwrap_or_else(Uuid::now_v7);
+                    tx.execute(
+                        r#"INSERT INTO notes (id, title, text, created_at, created_by, updated_at, updated_by, due_at, remind_at)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                        params![
+                            note_id,
+                            title,
+                            text,
+                            created_at.unwrap_or_else(chrono::Utc::now),
+                            user_id,
+                            updated_at,
+                            updated_at.and(user_id),
+                            due_at,
+                            remind_at
+                        ],
+                    )?;
+                    replace_tags(&tx, note_id, &tags)?;
+                    update_links(&tx, note_id)?;
+                    mark_past_reminder(&tx, note_id)?;
+                    reminders_changed |= remind_at.is_some();
+                    response.created += 1;
+                }
+                Some((note, trashed)) if trashed || note.created_by != user_id => response.skipped += 1,
+                Some((mut note, _)) => {
+                    load_details(&tx, [&mut note])?;
+                    if note.title == title
+                        && note.text == text
+                        && note.due_at == due_at
+                        && note.remind_at == remind_at
+                        && same_tags(&note.tags, &tags)
+                    {
+                        response.skipped += 1;
+                        continue;
+                    }
+                    tx.execute(
+                        r#"UPDATE notes SET title = ?, text = ?, due_at = ?, remind_at = ?, updated_at = ?, updated_by = ?
+                        WHERE id = ?"#,
+                        params![
+                            title,
+                            text,
+                            due_at,
+                            remind_at,
+                            updated_at.unwrap_or_else(chrono::Utc::now),
+                            user_id,
+                            note.id
+                        ],
+                    )?;
+                    set_tags(&tx, note.id, &tags)?;
+                    update_links(&tx, note.id)?;
+                    mark_past_reminder(&tx, note.id)?;
+                    reminders_changed |= note.remind_at != remind_at;
+                    response.updated += 1;
+                }
+            }
+        }
+
+        tx.commit()?;
+        if reminders_changed {
+            reminders::reschedule();
+        }
+        Ok(response)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Imported reminders in the past count as fired, they were when the notes were exported.
+fn mark_past_reminder(conn: &Connection, note_id: Uuid) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE notes SET reminded_at = remind_at WHERE id = ? AND remind_at <= ? AND reminded_at IS NULL",
+        params![note_id, chrono::Utc::now()],
+    )
+}
+
+fn same_tags(a: &[String], b: &[String]) -> bool {
+    let names = |tags: &[String]| tags.iter().map(|tag| tag.to_lowercase()).collect::<BTreeSet<_>>();
+    names(a) == names(b)
+}
+
+fn unexpected(error: impl std::error::Error) -> Error {
+    Error::Unexpected(error.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRecord {
+    id: Option<Uuid>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text: String,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    due_at: Option<chrono::DateTime<chrono::Utc>>,
+    remind_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Comma separated, tag names can't contain commas.
+    #[serde(default)]
+    tags: String,
+}
+
+impl From<NoteRecord> for CsvRecord {
+    fn from(record: NoteRecord) -> Self {
+        Self {
+            id: record.id,
+            title: record.title,
+            text: record.text,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            due_at: record.due_at,
+            remind_at: record.remind_at,
+            tags: record.tags.join(","),
+        }
+    }
+}
+
+impl From<CsvRecord> for NoteRecord {
+    fn from(record: CsvRecord) -> Self {
+        Self {
+            id: record.id,
+            title: record.title,
+            text: record.text,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            due_at: record.due_at,
+            remind_at: record.remind_at,
+            tags: record
+                .tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// The fields of a note besides its text, at the top of a Markdown file.
+#[derive(Debug, Serialize, Deserialize)]
+struct FrontMatter {
+    id: Option<Uuid>,
+    #[serde(default)]
+    title: String,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    due_at: Option<chrono::DateTime<chrono::Utc>>,
+    remind_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn to_markdown(record: NoteRecord) -> Result<String> {
+    let NoteRecord {
+        id,
+        title,
+        text,
+        created_at,
+        updated_at,
+        due_at,
+        remind_at,
+        tags,
+    } = record;
+    let front_matter = serde_yaml::to_string(&FrontMatter {
+        id,
+        title,
+        created_at,
+        updated_at,
+        due_at,
+        remind_at,
+        tags,
+    })
+    .map_err(unexpected)?;
+    Ok(format!("---\n{front_matter}---\n{text}"))
+}
+
+/// Splits the front matter between `---` lines off the text, lines may end with `\n` or `\r\n`.
+fn split_front_matter(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix("---")?;
+    let newline = ["\n", "\r\n"].into_iter().find(|newline| rest.starts_with(newline))?;
+    let rest = &rest[newline.len()..];
+
+    let delimiter = format!("---{newline}");
+    let end = if rest.starts_with(&delimiter) {
+        0
+    } else {
+        rest.find(&format!("{newline}{delimiter}"))? + newline.len()
+    };
+    Some((&rest[..end], &rest[end + delimiter.len()..]))
+}
+
+/// Files without front matter become notes titled after the file.
+fn from_markdown(file_name: &str, content: &str) -> Result<NoteRecord> {
+    let Some((front_matter, text)) = split_front_matter(content) else {
+        let title = file_name.rsplit('/').next().unwrap_or_default().trim_end_matches(".md");
+        return Ok(NoteRecord {
+            id: None,
+            title: title.into(),
+            text: content.into(),
+            created_at: None,
+            updated_at: None,
+            due_at: None,
+            remind_at: None,
+            tags: Vec::new(),
+        });
+    };
+
+    let FrontMatter {
+        id,
+        title,
+        created_at,
+        updated_at,
+        due_at,
+        remind_at,
+        tags,
+    } = serde_yaml::from_str(front_matter).map_err(|e| Error::Validation(format!("{file_name}: {e}")))?;
+    Ok(NoteRecord {
+        id,
+        title,
+        text: text.into(),
+        created_at,
+        updated_at,
+        due_at,
+        remind_at,
+        tags,
+    })
+}
+
+fn parse_records(format: TransferFormat, content: &[u8], max_bytes: u64) -> Result<Vec<NoteRecord>> {
+    match format {
+        TransferFormat::Ndjson => std::str::from_utf8(content)
+            .map_err(|_| Error::Validation("Expected UTF-8".into()))?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| Error::Validation(format!("Line {}: {e}", index + 1)))
+            })
+            .collect(),
+        TransferFormat::Csv => csv::Reader::from_reader(content)
+            .deserialize::<CsvRecord>()
+            .map(|record| {
+                record
+                    .map(NoteRecord::from)
+                    .map_err(|e| Error::Validation(format!("Invalid CSV: {e}")))
+            })
+            .collect(),
+        TransferFormat::Markdown => {
+            let invalid = |e: zip::result::ZipError| Error::Validation(format!("Invalid zip: {e}"));
+            let mut archive = ZipArchive::new(Cursor::new(content)).map_err(invalid)?;
+            let mut records = Vec::new();
+            // the sizes in the archive can't be trusted, the reads are bounded instead
+            let mut remaining = max_bytes;
+            for index in 0..archive.len() {
+                let file = archive.by_index(index).map_err(invalid)?;
+                if !file.is_file() || !file.name().ends_with(".md") {
+                    continue;
+                }
+                let file_name = file.name().to_string();
+                let mut content = Vec::new();
+                file.take(remaining + 1)
+                    .read_to_end(&mut content)
+                    .map_err(|e| Error::Validation(format!("{file_name}: {e}")))?;
+                remaining = remaining
+                    .checked_sub(content.len() as u64)
+                    .ok_or_else(|| Error::PayloadTooLarge(format!("Unzipped notes exceed {max_bytes} bytes")))?;
+                let content = String::from_utf8(content)
+                    .map_err(|_| Error::Validation(format!("{file_name}: expected UTF-8")))?;
+                records.push(from_markdown(&file_name, &content)?);
+            }
+            Ok(records)
+        }
+    }
+}
+
+enum Encoder {
+    Ndjson,
+    Csv {
+        headers: bool,
+    },
+    Markdown {
+        zip: Box<ZipWriter<Spool>>,
+        spool: Spool,
+        /// Where the entry that is still written to starts.
+        open: u64,
+    },
+}
+
+impl Encoder {
+    fn new(format: TransferFormat) -> Self {
+        match format {
+            TransferFormat::Ndjson => Self::Ndjson,
+            TransferFormat::Csv => Self::Csv { headers: true },
+            TransferFormat::Markdown => {
+                let spool = Spool::default();
+                Self::Markdown {
+                    zip: Box::new(ZipWriter::new(spool.clone())),
+                    spool,
+                    open: 0,
+                }
+            }
+        }
+    }
+
+    /// Bytes of the records that are ready to be sent.
+    fn encode(&mut self, records: impl Iterator<Item = NoteRecord>) -> Result<Vec<u8>> {
+        match self {
+            Self::Ndjson => {
+                let mut chunk = Vec::new();
+                for record in records {
+                    serde_json::to_writer(&mut chunk, &record).map_err(unexpected)?;
+                    chunk.push(b'\n');
+                }
+                Ok(chunk)
+            }
+            Self::Csv { headers } => {
+                let mut writer = csv::WriterBuilder::new().has_headers(*headers).from_writer(Vec::new());
+                *headers = false;
+                for record in records {
+                    writer.serialize(CsvRecord::from(record)).map_err(unexpected)?;
+                }
+                writer.into_inner().map_err(|e| unexpected(e.into_error()))
+            }
+            Self::Markdown { zip, spool, open } => {
+                for record in records {
+                    // starting an entry completes the previous one
+                    let start = spool.len();
+                    let file_name = format!("{}.md", record.id.unwrap_or_default());
+                    zip.start_file(file_name, SimpleFileOptions::default())
+                        .map_err(unexpected)?;
+                    *open = start;
+                    zip.write_all(to_markdown(record)?.as_bytes()).map_err(unexpected)?;
+                }
+                Ok(spool.take(*open))
+            }
+        }
+    }
+
+    /// The rest of the export.
+    fn finish(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Ndjson | Self::Csv { .. } => Ok(Vec::new()),
+            Self::Markdown { zip, spool, .. } => {
+                zip.finish().map_err(unexpected)?;
+                Ok(spool.take(spool.len()))
+            }
+        }
+    }
+}
+
+/// Seekable end of a zip archive that is sent while it's written. Zip seeks back to an entry's
+/// header once the entry is complete, so only complete entries are taken out.
+#[derive(Clone, Default)]
+struct Spool(Arc<Mutex<SpoolState>>);
+
+#[derive(Default)]
+struct SpoolState {
+    /// Length of the part taken out already.
+    taken: u64,
+    buffer: Vec<u8>,
+    position: u64,
+}
+
+impl Spool {
+    fn lock(&self) -> MutexGuard<'_, SpoolState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn len(&self) -> u64 {
+        let state = self.lock();
+        state.taken + state.buffer.len() as u64
+    }
+
+    /// Takes out the bytes before `end`, they can't be written to afterwards.
+    fn take(&self, end: u64) -> Vec<u8> {
+        let mut state = self.lock();
+        let len = end.saturating_sub(state.taken) as usize;
+        state.taken += len as u64;
+        state.buffer.drain(..len).collect()
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        let start = state
+            .position
+            .checked_sub(state.taken)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "write to a part taken out"))?
+            as usize;
+        let end = start + data.len();
+        if state.buffer.len() < end {
+            state.buffer.resize(end, 0);
+        }
+        state.buffer[start..end].copy_from_slice(data);
+        state.position += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let mut state = self.lock();
+        let position = match position {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => (state.taken + state.buffer.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => state.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) if position >= state.taken => {
+                state.position = position;
+                Ok(position)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek to a part taken out")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_front_matter() -> Result<()> {
+        let remind_at = chrono::Utc::now();
+        let record = NoteRecord {
+            id: Some(Uuid::now_v7()),
+            title: "a: title".into(),
+            text: "---\ntext\n".into(),
+            created_at: Some(chrono::Utc::now()),
+            updated_at: None,
+            due_at: None,
+            remind_at: Some(remind_at),
+            tags: vec!["a".into(), "b".into()],
+        };
+        let markdown = to_markdown(record)?;
+        let record = from_markdown("note.md", &markdown)?;
+        assert_eq!(
+            (record.title.as_str(), record.text.as_str()),
+            ("a: title", "---\ntext\n")
+        );
+        assert_eq!(record.tags, ["a", "b"]);
+        assert_eq!(record.remind_at, Some(remind_at));
+
+        let record = from_markdown("windows.md", "---\r\ntitle: crlf\r\ntags: [a]\r\n---\r\ntext\r\n")?;
+        assert_eq!((record.title.as_str(), record.text.as_str()), ("crlf", "text\r\n"));
+        assert_eq!(record.tags, ["a"]);
+
+        let record = from_markdown("notes/plain.md", "no front matter")?;
+        assert_eq!(
+            (record.title.as_str(), record.text.as_str()),
+            ("plain", "no front matter")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferFormat {
+    /// One JSON note per line.
+    #[default]
+    Ndjson,
+    /// A header row, tags are comma separated.
+    Csv,
+    /// A zip of `{id}.md` files, with the other fields in YAML front matter.
+    Markdown,
+}
+
+impl TransferFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Markdown => "application/zip",
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "notes.ndjson",
+            Self::Csv => "notes.csv",
+            Self::Markdown => "notes.zip",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TransferNotes {
+    #[serde(default)]
+    pub format: TransferFormat,
+}
+
+/// A note as exported. On import `id` and the timestamps are optional, notes without an `id` are created.
+/// An `id` that is not a UUIDv7 is replaced by a new one.
+#[derive(Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct NoteRecord {
+    pub id: Option<Uuid>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub text: String,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub due_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// A reminder in the past counts as fired on import.
+    pub remind_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
+pub struct ImportNotesResponse {
+    pub created: u32,
+    pub updated: u32,
+    /// Notes that are unchanged, trashed, or belong to another user.
+    pub skipped: u32,
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use crate::{
+    config::Config,
+    openapi::{
+        aide::axum::{
+            routing::{get_with, post_with},
+            ApiRouter, IntoApiResponse,
+        },
+        Json, Query, TransformOperationExt,
+    },
+    policy::{
+        policies::{NotesRead, NotesWrite},
+        Authorized,
+    },
+    state::AppState,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use super::{handlers, TransferNotes};
+
+pub fn router(state: AppState) -> ApiRouter {
+    let import_max_bytes = state.config.import_max_bytes as usize;
+    ApiRouter::new()
+        .api_route(
+            "/api/v1/notes/export",
+            get_with(export_notes, |t| {
+                t.description("Streams the caller's notes as a file, trashed notes are left out.")
+            }),
+        )
+        .api_route(
+            "/api/v1/notes/import",
+            post_with(import_notes, |t| {
+                t.description(
+                    "Imports a file in an export format. Notes are matched by id: \
+                    the caller's notes are updated, new ids and notes without one are created. \
+                    Trashed notes are skipped, ids that are not UUIDv7 are replaced by new ones.",
+                )
+                .error::<400>("Malformed file, or an invalid note")
+                .error::<413>("File too large, or too large once unzipped")
+            })
+            .layer(DefaultBodyLimit::max(import_max_bytes)),
+        )
+        .with_state(state)
+}
+
+async fn export_notes(
+    Query(TransferNotes { format }): Query<TransferNotes>,
+    Authorized(base, _): Authorized<NotesRead>,
+) -> Response {
+    let content = handlers::export_notes(format, base);
+    let disposition = format!("attachment; filename=\"{}\"", format.file_name());
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(content),
+    )
+        .into_response()
+}
+
+async fn import_notes(
+    Query(TransferNotes { format }): Query<TransferNotes>,
+    State(config): State<Arc<Config>>,
+    Authorized(base, _): Authorized<NotesWrite>,
+    content: Bytes,
+) -> impl IntoApiResponse {
+    handlers::import_notes(format, content, config.import_max_bytes, base)
+        .await
+        .map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use axum_test::TestServer;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use crate::{
+        config::Config,
+        db::{init_test_db, DB},
+        errors::Result,
+        tests::{test_config, test_server_with_config},
+        transfer::{ImportNotesResponse, NoteRecord},
+    };
+
+    async fn test_db() -> Result<DB> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'other@mail.com', 'active');
+                WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 150)
+                INSERT INTO notes (title, text, created_by) SELECT 'note ' || i, 'text ' || i, uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af') FROM n;
+                INSERT INTO notes (id, title, text, created_by, deleted_at) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000001'), 'trashed', '', uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), CURRENT_TIMESTAMP);
+                INSERT INTO notes (id, title, text, created_by) VALUES (uuid_blob('018f6138-5b4f-722d-97c5-000000000002'), 'other', '', uuid_blob('018f6146-32f4-7948-8289-000000000002'));
+                INSERT INTO tags (user_id, name) VALUES (uuid_blob('018f6146-32f4-7948-8289-cfb5cdb2b2af'), 'work');
+                INSERT INTO note_tags (note_id, tag_id) SELECT notes.id, tags.id FROM notes, tags WHERE notes.title = 'note 1';
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        Ok(db)
+    }
+
+    #[tokio::test]
+    async fn export_formats() -> Result<()> {
+        let server = test_server(test_db().await?).await?;
+
+        let response = server.get("/api/v1/notes/export").await;
+        assert_eq!(response.header("content-type"), "application/x-ndjson");
+        let records = response
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str::<NoteRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 150);
+        assert_eq!(
+            (records[0].title.as_str(), records[0].tags.as_slice()),
+            ("note 1", ["work".to_string()].as_slice())
+        );
+
+        let response = server.get("/api/v1/notes/export?format=csv").await;
+        let csv = response.text();
+        assert!(csv.starts_with("id,title,text,created_at,updated_at,due_at,remind_at,tags\n"));
+        assert_eq!(csv::Reader::from_reader(csv.as_bytes()).records().count(), 150);
+
+        let response = server.get("/api/v1/notes/export?format=markdown").await;
+        assert_eq!(response.header("content-type"), "application/zip");
+        let mut archive = zip::ZipArchive::new(Cursor::new(response.as_bytes().to_vec())).unwrap();
+        assert_eq!(archive.len(), 150);
+        let mut markdown = String::new();
+        archive
+            .by_name(&format!("{}.md", records[0].id.unwrap()))
+            .unwrap()
+            .read_to_string(&mut markdown)
+            .unwrap();
+        assert!(markdown.starts_with("---\n"));
+        assert!(markdown.contains("title: note 1\n"));
+        assert!(markdown.ends_with("---\ntext 1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn import_notes() -> Result<()> {
+        let server = test_server(test_db().await?).await?;
+
+        let ndjson = server.get("/api/v1/notes/export").await.text();
+        let mut lines = ndjson.lines().map(String::from).collect::<Vec<_>>();
+        lines[0] = lines[0].replace("\"note 1\"", "\"first note\"");
+        lines.push(
+            r#"{"title": "new", "tags": ["imported"], "due_at": "2030-01-01T09:00:00Z", "remind_at": "2030-01-01T08:00:00Z"}"#
+                .into(),
+        );
+        lines.push(r#"{"id": "018f6138-5b4f-722d-97c5-000000000002", "title": "not mine"}"#.into());
+
+        let response = server
+            .post("/api/v1/notes/import")
+            .bytes(lines.join("\n").into())
+            .await
+            .json::<ImportNotesResponse>();
+        assert_eq!((response.created, response.updated, response.skipped), (1, 1, 150));
+
+        // markdown round trip into another instance
+        let markdown = server
+            .get("/api/v1/notes/export?format=markdown")
+            .await
+            .as_bytes()
+            .clone();
+        let other = test_server(init_test_db().await?).await?;
+        let response = other
+            .post("/api/v1/notes/import?format=markdown")
+            .bytes(markdown)
+            .await
+            .json::<ImportNotesResponse>();
+        assert_eq!(response.created, 151);
+        let exported = other.get("/api/v1/notes/export").await.text();
+        assert!(exported.contains(r#""title":"first note""#));
+        assert!(exported.contains(r#""tags":["imported"]"#));
+        assert!(exported.contains(r#""due_at":"2030-01-01T09:00:00Z","remind_at":"2030-01-01T08:00:00Z""#));
+
+        let response = server
+            .post("/api/v1/notes/import?format=csv")
+            .bytes("id,title\nnot-a-uuid,x\n".into())
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn import_edge_cases() -> Result<()> {
+        let server = test_server(test_db().await?).await?;
+
+        // trashed notes stay trashed, ids that are not v7 get a new one
+        let lines = [
+            r#"{"id": "018f6138-5b4f-722d-97c5-000000000001", "title": "restored?"}"#,
+            r#"{"id": "5f0c2b7e-8b1d-4c3a-9f2e-1d2c3b4a5f6e", "title": "v4"}"#,
+        ];
+        let response = server
+            .post("/api/v1/notes/import")
+            .bytes(lines.join("\n").into())
+            .await
+            .json::<ImportNotesResponse>();
+        assert_eq!((response.created, response.updated, response.skipped), (1, 0, 1));
+
+        let exported = server.get("/api/v1/notes/export").await.text();
+        assert!(!exported.contains("restored?"));
+        let imported = exported
+            .lines()
+            .map(|line| serde_json::from_str::<NoteRecord>(line).unwrap())
+            .find(|record| record.title == "v4")
+            .unwrap();
+        assert_eq!(imported.id.unwrap().get_version_num(), 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_zip_bombs() -> Result<()> {
+        let config = Config {
+            import_max_bytes: 64 * 1024,
+            ..test_config()
+        };
+        let server = test_server_with_config(init_test_db().await?, config, super::router).await?;
+
+        // compresses far below the limit
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["a.md", "b.md"] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(&vec![b'a'; 40 * 1024]).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+        assert!(zip.len() < 4 * 1024);
+
+        let response = server
+            .post("/api/v1/notes/import?format=markdown")
+            .bytes(zip.into())
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 413);
+        Ok(())
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, super::router).await
+    }
+}
@@ -0,0 +1,106 @@
+use rusqlite::params;
+use uuid::Uuid;
+
+use crate::{
+    auth,
+    ctx::{BaseParams, User, UserStatus},
+    db, Error, Result, DB,
+};
+
+use super::{FindUsers, FindUsersResponse, SetRole};
+
+pub async fn find_users(
+    FindUsers { status }: FindUsers,
+    BaseParams { db, ctx }: BaseParams,
+) -> Result<FindUsersResponse> {
+    db.call(move |conn| {
+        let users = conn
+            .prepare("SELECT id, email, role, status FROM users WHERE (?1 IS NULL OR status = ?1) ORDER BY id")?
+            .query_map(params![status], |row| User::try_from(row))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(FindUsersResponse { results: users })
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(Error::from)
+}
+
+/// Lets a wait-listed user in.
+pub async fn approve_user(user_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<User> {
+    let admin_id = ctx.get_user_id();
+    transition(db, user_id, UserStatus::Pending, UserStatus::Active, admin_id).await
+}
+
+/// Blocks a user and revokes all of their credentials.
+pub async fn block_user(user_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<User> {
+    let admin_id = ctx.get_user_id();
+    if admin_id == Some(user_id) {
+        return Err(Error::Validation("You can't block yourself".into()));
+    }
+
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let user = tx.query_row(
+            r#"UPDATE users SET status = ?, updated_at = ?, updated_by = ?
+            WHERE id = ?
+            RETURNING id, email, role, status"#,
+            params![UserStatus::Blocked, chrono::Utc::now(), admin_id, user_id],
+            |row| User::try_from(row),
+        )?;
+        auth::revoke_user_credentials(&tx, user_id)?;
+        tx.commit()?;
+        Ok(user)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "User not found"))
+    .map_err(Error::from)
+}
+
+/// Changes a user's role. Their sessions are ended, see [`auth::end_user_sessions`].
+pub async fn set_role(user_id: Uuid, SetRole { role }: SetRole, BaseParams { db, ctx }: BaseParams) -> Result<User> {
+    let admin_id = ctx.get_user_id();
+    if admin_id == Some(user_id) {
+        return Err(Error::Validation("You can't change your own role".into()));
+    }
+
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let user = tx.query_row(
+            r#"UPDATE users SET role = ?, updated_at = ?, updated_by = ?
+            WHERE id = ?
+            RETURNING id, email, role, status"#,
+            params![role, chrono::Utc::now(), admin_id, user_id],
+            |row| User::try_from(row),
+        )?;
+        auth::end_user_sessions(&tx, user_id)?;
+        tx.commit()?;
+        Ok(user)
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, "User not found"))
+    .map_err(Error::from)
+}
+
+pub async fn unblock_user(user_id: Uuid, BaseParams { db, ctx }: BaseParams) -> Result<User> {
+    let admin_id = ctx.get_user_id();
+    transition(db, user_id, UserStatus::Blocked, UserStatus::Active, admin_id).await
+}
+
+async fn transition(db: DB, user_id: Uuid, from: UserStatus, to: UserStatus, updated_by: Option<Uuid>) -> Result<User> {
+    db.call(move |conn| {
+        conn.query_row(
+            r#"UPDATE users SET status = ?, updated_at = ?, updated_by = ?
+            WHERE id = ? AND status = ?
+            RETURNING id, email, role, status"#,
+            params![to, chrono::Utc::now(), updated_by, user_id, from],
+            |row| User::try_from(row),
+        )
+        .map_err(|e| e.into())
+    })
+    .await
+    .map_err(db::Error::from)
+    .map_err(|e| db::Error::not_found_message(e, format!("No {} user with this id", from.as_str())))
+    .map_err(Error::from)
+}
@@ -0,0 +1,19 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ctx::{Role, User, UserStatus};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindUsers {
+    pub status: Option<UserStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FindUsersResponse {
+    pub results: Vec<User>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetRole {
+    pub role: Role,
+}
@@ -0,0 +1,218 @@
+use crate::{
+    ctx::User,
+    openapi::{
+        aide::axum::{
+            routing::{get, post_with},
+            ApiRouter, IntoApiResponse,
+        },
+        Json, Path, Query, TransformOperationExt,
+    },
+    policy::{policies::UsersManage, Authorized},
+    state::AppState,
+};
+
+use schemars::JsonSchema;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{handlers, FindUsers, SetRole};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct UserIdPath {
+    user_id: Uuid,
+}
+
+pub fn router(state: AppState) -> ApiRouter {
+    ApiRouter::new()
+        .api_route("/api/v1/users", get(find_users))
+        .api_route(
+            "/api/v1/users/{user_id}/approve",
+            post_with(approve_user, |t| {
+                t.description("Activates a `pending` user.")
+                    .error::<404>("No pending user with this id")
+            }),
+        )
+        .api_route(
+            "/api/v1/users/{user_id}/block",
+            post_with(block_user, |t| {
+                t.description("Blocks a user and revokes all of their sessions.")
+                    .error::<404>("User not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/users/{user_id}/role",
+            post_with(set_role, |t| {
+                t.description("Changes a user's role. Their browser sessions are issued new tokens.")
+                    .error::<400>("Changing your own role")
+                    .error::<404>("User not found")
+            }),
+        )
+        .api_route(
+            "/api/v1/users/{user_id}/unblock",
+            post_with(unblock_user, |t| {
+                t.description("Reactivates a `blocked` user.")
+                    .error::<404>("No blocked user with this id")
+            }),
+        )
+        .with_state(state)
+}
+
+async fn find_users(
+    Query(args): Query<FindUsers>,
+    Authorized(base, _): Authorized<UsersManage>,
+) -> impl IntoApiResponse {
+    handlers::find_users(args, base).await.map(Json)
+}
+
+async fn approve_user(
+    Path(UserIdPath { user_id }): Path<UserIdPath>,
+    Authorized(base, _): Authorized<UsersManage>,
+) -> impl IntoApiResponse {
+    handlers::approve_user(user_id, base).await.map(Json::<User>)
+}
+
+async fn block_user(
+    Path(UserIdPath { user_id }): Path<UserIdPath>,
+    Authorized(base, _): Authorized<UsersManage>,
+) -> impl IntoApiResponse {
+    handlers::block_user(user_id, base).await.map(Json::<User>)
+}
+
+async fn set_role(
+    Path(UserIdPath { user_id }): Path<UserIdPath>,
+    Authorized(base, _): Authorized<UsersManage>,
+    Json(args): Json<SetRole>,
+) -> impl IntoApiResponse {
+    handlers::set_role(user_id, args, base).await.map(Json::<User>)
+}
+
+async fn unblock_user(
+    Path(UserIdPath { user_id }): Path<UserIdPath>,
+    Authorized(base, _): Authorized<UsersManage>,
+) -> impl IntoApiResponse {
+    handlers::unblock_user(user_id, base).await.map(Json::<User>)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_extra::extract::cookie::Cookie;
+    use axum_test::TestServer;
+    use serde_json::json;
+    use uuid::{uuid, Uuid};
+
+    use crate::{
+        auth,
+        ctx::{Role, User, UserStatus},
+        db::{init_test_db, DB},
+        errors::Result,
+        tests::authenticate,
+        users::FindUsersResponse,
+    };
+
+    const PENDING_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000001");
+    const MEMBER_USER_ID: Uuid = uuid!("018f6146-32f4-7948-8289-000000000002");
+
+    #[tokio::test]
+    async fn approve_pending_user() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db).await?;
+
+        let response = server.get("/api/v1/users?status=pending").await;
+        let pending = response.json::<FindUsersResponse>().results;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, PENDING_USER_ID);
+
+        let response = server.post(&format!("/api/v1/users/{PENDING_USER_ID}/approve")).await;
+        assert_eq!(response.json::<User>().status, UserStatus::Active);
+
+        let response = server
+            .post(&format!("/api/v1/users/{PENDING_USER_ID}/approve"))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn block_revokes_sessions() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let mut member = test_server(db.clone()).await?;
+        authenticate(&db, &mut member, MEMBER_USER_ID).await?;
+        member.get("/api/v1/users").expect_failure().await;
+
+        let response = server.post(&format!("/api/v1/users/{MEMBER_USER_ID}/block")).await;
+        assert_eq!(response.json::<User>().status, UserStatus::Blocked);
+
+        let response = member.get("/api/v1/users").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+
+        let response = server.post(&format!("/api/v1/users/{MEMBER_USER_ID}/unblock")).await;
+        assert_eq!(response.json::<User>().status, UserStatus::Active);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn admin_only() -> Result<()> {
+        let db = test_db().await?;
+        let mut server = test_server(db.clone()).await?;
+        authenticate(&db, &mut server, MEMBER_USER_ID).await?;
+
+        let response = server.get("/api/v1/users").expect_failure().await;
+        assert_eq!(response.status_code(), 403);
+
+        let response = server
+            .post(&format!("/api/v1/users/{PENDING_USER_ID}/approve"))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), 403);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn role_change_ends_sessions() -> Result<()> {
+        let db = test_db().await?;
+        let server = test_server(db.clone()).await?;
+
+        let token = auth::create_session(&db, MEMBER_USER_ID, None, 3600).await?;
+        let mut member = test_server(db.clone()).await?;
+        member.clear_headers();
+        member.add_cookie(Cookie::new(auth::SESSION_COOKIE, token.clone()));
+        let response = member.get("/api/v1/users").expect_failure().await;
+        assert_eq!(response.status_code(), 403);
+
+        let response = server
+            .post(&format!("/api/v1/users/{MEMBER_USER_ID}/role"))
+            .json(&json!({ "role": "admin" }))
+            .await;
+        assert_eq!(response.json::<User>().role, Role::Admin);
+
+        let response = member.get("/api/v1/users").expect_failure().await;
+        assert_eq!(response.status_code(), 401);
+        assert!(auth::find_user_by_token(&db, &token, 3600).await?.is_none());
+        Ok(())
+    }
+
+    async fn test_db() -> Result<DB> {
+        let db = init_test_db().await?;
+        db.call(|conn| {
+            conn.execute_batch(
+                r#"
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000001'), 'pending@mail.com', 'pending');
+                INSERT INTO users (id, email, status) VALUES (uuid_blob('018f6146-32f4-7948-8289-000000000002'), 'member@mail.com', 'active');
+                "#,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        Ok(db)
+    }
+
+    async fn test_server(db: DB) -> Result<TestServer> {
+        crate::tests::test_server(db, super::router).await
+    }
+}